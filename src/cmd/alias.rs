@@ -0,0 +1,44 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{context::Context, line::quote};
+
+#[derive(Parser)]
+#[command(bin_name = "alias")]
+struct Args {
+    #[arg(help = "Aliases to define (name=value) or to show (name), lists all aliases if empty")]
+    definitions: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+
+    if args.definitions.is_empty() {
+        let mut names: Vec<&String> = ctx.aliases.keys().collect();
+        names.sort();
+        return Ok(names.into_iter()
+            .map(|name| format_alias(name, &ctx.aliases[name]))
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    let mut shown = Vec::new();
+    for definition in args.definitions {
+        match definition.split_once('=') {
+            Some(("", _)) => bail!("alias: {}: invalid alias name", definition),
+            Some((name, value)) => {
+                ctx.aliases.insert(name.to_owned(), value.to_owned());
+            },
+            None => match ctx.aliases.get(&definition) {
+                Some(value) => shown.push(format_alias(&definition, value)),
+                None => bail!("alias: {}: not found", definition),
+            },
+        }
+    }
+    Ok(shown.join("\n"))
+}
+
+/// Formats the alias such that it can be redefined with the output.
+fn format_alias(name: &str, value: &str) -> String {
+    format!("alias {}={}", name, quote(value))
+}
@@ -7,13 +7,15 @@ use crate::{context::Context, path::VirtualPathBuf};
 #[derive(Parser)]
 #[command(bin_name = "cat")]
 struct Args {
-    #[arg(default_value = ".", help = "The resource to output")]
-    path: VirtualPathBuf,
+    #[arg(help = "The resource to output (defaults to the piped input or .)")]
+    path: Option<VirtualPathBuf>,
 }
 
-pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(args: &[String], input: Option<Value>, ctx: &mut Context) -> Result<Value> {
     let args = Args::try_parse_from(args)?;
-    let path = ctx.cwd.join(args.path);
-    let result: Value = ctx.lh.get(&path.as_lh_vec()).await?.payload;
-    Ok(format!("{}", result))
+    if let (None, Some(input)) = (&args.path, input) {
+        return Ok(input);
+    }
+    let path = ctx.cwd.join(args.path.unwrap_or_else(|| VirtualPathBuf::from(".")));
+    Ok(ctx.lh.get(&path.as_lh_vec()).await?.payload)
 }
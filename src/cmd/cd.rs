@@ -10,7 +10,7 @@ struct Args {
     path: VirtualPathBuf,
 }
 
-pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
     let args = Args::try_parse_from(args)?;
     let new_cwd = ctx.cwd.join(args.path);
     ctx.lh.list(&new_cwd.as_lh_vec()).await?;
@@ -1,6 +1,6 @@
 use crate::{client_id::CLIENT_ID, context::Context, path::VirtualPathBuf};
 use anyhow::Result;
-use clap::{command, Parser};
+use clap::Parser;
 use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind};
 use futures::{select, StreamExt};
 use lighthouse_client::protocol::{EventSource, Frame, InputEvent, KeyEvent, KeyModifiers, LegacyInputEvent, Model, LIGHTHOUSE_COLS, LIGHTHOUSE_ROWS};
@@ -35,7 +35,7 @@ struct Args {
     path: VirtualPathBuf,
 }
 
-pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
     let args = Args::try_parse_from(args)?;
     let path = ctx.cwd.join(args.path);
 
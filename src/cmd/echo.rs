@@ -2,6 +2,6 @@ use anyhow::Result;
 
 use crate::context::Context;
 
-pub async fn invoke(args: &[String], _ctx: &mut Context) -> Result<String> {
-    Ok(format!("{}", args[1..].join(" ")))
+pub async fn invoke(args: &[String], _input: Option<String>, _ctx: &mut Context) -> Result<String> {
+    Ok(args[1..].join(" "))
 }
@@ -0,0 +1,10 @@
+use anyhow::Result;
+
+use crate::context::Context;
+
+pub async fn invoke(_args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    Ok(ctx.environment().into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -0,0 +1,40 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{context::Context, line::quote, value};
+
+#[derive(Parser)]
+#[command(bin_name = "export")]
+struct Args {
+    #[arg(help = "Variables to export (name or name=value), lists exported variables if empty")]
+    variables: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+
+    if args.variables.is_empty() {
+        let mut names: Vec<&String> = ctx.exported.iter().collect();
+        names.sort();
+        return Ok(names.into_iter()
+            .map(|name| match ctx.variables.get(name) {
+                Some(value) => format!("export {}={}", name, quote(&value::to_text(value))),
+                None => format!("export {}", name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    for variable in args.variables {
+        let name = match variable.split_once('=') {
+            Some(("", _)) => bail!("export: {}: invalid variable name", variable),
+            Some((name, value)) => {
+                ctx.variables.insert(name.to_owned(), value.into());
+                name.to_owned()
+            },
+            None => variable,
+        };
+        ctx.exported.insert(name);
+    }
+    Ok(String::new())
+}
@@ -0,0 +1,24 @@
+use anyhow::{Error, Result};
+
+use crate::{context::Context, line, status::ExitStatus};
+
+/// Evaluates the arguments as a single arithmetic expression and outputs the
+/// result (e.g. `expr 1 + 2`).
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let value = line::evaluate_arithmetic(&args[1..].join(" "), ctx)?;
+    Ok(value.to_string())
+}
+
+/// Evaluates each argument as an arithmetic expression, usually to assign
+/// variables (e.g. `let x=x+1`). Like in POSIX shells, fails if the last
+/// result is 0, so it can be used as a condition (e.g. `while let "x < 10"`).
+pub async fn invoke_let(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let mut value = 0;
+    for expression in &args[1..] {
+        value = line::evaluate_arithmetic(expression, ctx)?;
+    }
+    if value == 0 {
+        return Err(Error::new(ExitStatus::FAILURE));
+    }
+    Ok(String::new())
+}
@@ -0,0 +1,32 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::context::Context;
+
+#[derive(Parser)]
+#[command(bin_name = "history")]
+struct Args {
+    #[arg(short = 'n', long, help = "Only list the last n entries")]
+    count: Option<usize>,
+
+    #[arg(help = "Only list entries containing this text")]
+    query: Option<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+    let mut entries: Vec<_> = ctx.history.iter()
+        .enumerate()
+        .filter(|(_, entry)| args.query.as_ref().is_none_or(|q| entry.contains(q.as_str())))
+        .collect();
+
+    if let Some(count) = args.count {
+        entries.drain(..entries.len().saturating_sub(count));
+    }
+
+    // Entries are numbered from 1, matching the !n history expansion
+    Ok(entries.into_iter()
+        .map(|(i, entry)| format!("{:<5} {}", i + 1, entry))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
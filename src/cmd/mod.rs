@@ -1,27 +1,51 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Error, Result};
+use lighthouse_client::protocol::Value;
 
-use crate::context::Context;
+use crate::{context::Context, line, status::ExitStatus, value};
 
 macro_rules! cmd_mods {
-    ($($mod:ident),* $(,)?) => {
+    ($($mod:ident),* $(,)?; typed: $($typed_mod:ident),* $(,)?) => {
         $(mod $mod;)*
+        $(mod $typed_mod;)*
 
-        pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+        /// The names of the built-in commands.
+        pub const COMMANDS: &[&str] = &[$(stringify!($mod),)* $(stringify!($typed_mod),)* "help", "jq", "let"];
+
+        /// Invokes the command named by the first argument, which is either a
+        /// built-in command or a user-defined function. The input, if
+        /// present, is the output of the previous command in a pipeline.
+        /// Typed commands take and return values as-is, all others text.
+        pub async fn invoke(args: &[String], input: Option<Value>, ctx: &mut Context) -> Result<Value> {
             Ok(match args[0].as_str() {
-                $(stringify!($mod) => $mod::invoke(args, ctx).await?,)*
-                "help" => bail!("Available commands: {}", [$(stringify!($mod),)*].join(", ")),
-                cmd => bail!("Unrecognized command: {}", cmd),
+                $(stringify!($typed_mod) => $typed_mod::invoke(args, input, ctx).await?,)*
+                $(stringify!($mod) => $mod::invoke(args, text(input), ctx).await?.into(),)*
+                "jq" => query::invoke(args, input, ctx).await?,
+                "help" => bail!("Available commands: {}", COMMANDS.join(", ")),
+                // Since let is a Rust keyword, it is implemented in the expr module
+                "let" => expr::invoke_let(args, text(input), ctx).await?.into(),
+                cmd => match ctx.functions.get(cmd).cloned() {
+                    Some(body) => line::call_function(body, args, ctx).await?,
+                    None => return Err(Error::new(ExitStatus::NOT_FOUND).context(format!("Unrecognized command: {}", cmd))),
+                },
             })
         }
     };
 }
 
+fn text(input: Option<Value>) -> Option<String> {
+    input.as_ref().map(value::to_text)
+}
+
 cmd_mods! {
-    cat,
+    alias,
     cd,
     cp,
     display,
     echo,
+    env,
+    export,
+    expr,
+    history,
     ln,
     ls,
     mkdir,
@@ -29,7 +53,16 @@ cmd_mods! {
     pwd,
     rm,
     rmdir,
+    set,
     touch,
     tree,
     uln,
+    unalias,
+    unset,
+    wc;
+    typed:
+    cat,
+    patch,
+    query,
+    source,
 }
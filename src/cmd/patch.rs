@@ -0,0 +1,70 @@
+use anyhow::{bail, Context as _, Result};
+use clap::Parser;
+use lighthouse_client::protocol::{to_value, Value};
+
+use crate::{context::Context, filter, path::VirtualPathBuf, value};
+
+#[derive(Parser)]
+#[command(bin_name = "patch")]
+struct Args {
+    #[arg(short, long, conflicts_with = "json_patch", help = "Apply a JSON merge patch (RFC 7386)")]
+    merge: bool,
+
+    #[arg(short, long, help = "Apply a JSON Patch (RFC 6902)")]
+    json_patch: bool,
+
+    #[arg(short = 'u', long, help = "Fail if the resource changed while patching it")]
+    if_unchanged: bool,
+
+    #[arg(help = "The resource to patch")]
+    path: VirtualPathBuf,
+
+    #[arg(allow_hyphen_values = true, help = "The field and its new value (e.g. .config.speed 3), or the patch with --merge/--json-patch (defaults to the piped input)")]
+    args: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], input: Option<Value>, ctx: &mut Context) -> Result<Value> {
+    let args = Args::try_parse_from(args)?;
+    let resource = ctx.cwd.join(&args.path);
+    let path = resource.as_lh_vec();
+    let original: Value = ctx.lh.get(&path).await?.payload;
+
+    let mut patched = original.clone();
+    if args.merge || args.json_patch {
+        let patch = match (args.args.as_slice(), input) {
+            ([patch], _) => parse_json(patch)?,
+            ([], Some(Value::String(s))) => parse_json(&String::from_utf8_lossy(s.as_bytes()))?,
+            ([], Some(input)) => input,
+            ([], None) => bail!("Please specify a patch or pipe one in"),
+            _ => bail!("Please specify a single patch"),
+        };
+        if args.merge {
+            value::merge_patch(&mut patched, patch);
+        } else {
+            value::json_patch(&mut patched, &patch)?;
+        }
+    } else {
+        let [field, new_value] = args.args.as_slice() else {
+            bail!("Please specify a field and its new value, e.g. patch {} .config.speed 3", args.path);
+        };
+        let field_path = filter::parse(field)?.as_path()
+            .with_context(|| format!("{} is not a field path like .config.speed", field))?;
+        // Like with redirects, values that are not valid JSON are used as strings
+        let new_value = parse_json(new_value).unwrap_or_else(|_| Value::from(new_value.as_str()));
+        value::set_path(&mut patched, &field_path, new_value)?;
+    }
+
+    // The model server has no conditional writes, so we can only narrow down
+    // the window in which a concurrent change goes unnoticed
+    if args.if_unchanged && ctx.lh.get::<Value>(&path).await?.payload != original {
+        bail!("{} changed while patching it", args.path);
+    }
+    ctx.lh.put(&path, patched).await?;
+    Ok(String::new().into())
+}
+
+fn parse_json(text: &str) -> Result<Value> {
+    let json_value: serde_json::Value = serde_json::from_str(text)
+        .with_context(|| format!("Could not parse {} as JSON", text))?;
+    Ok(to_value(json_value)?)
+}
@@ -2,6 +2,6 @@ use anyhow::Result;
 
 use crate::context::Context;
 
-pub async fn invoke(_args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(_args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
     Ok(format!("{}", ctx.cwd))
 }
@@ -0,0 +1,55 @@
+use anyhow::{bail, Context as _, Result};
+use clap::Parser;
+use lighthouse_client::protocol::{to_value, Value};
+
+use crate::{context::Context, filter, path::VirtualPathBuf, value};
+
+#[derive(Parser)]
+#[command(bin_name = "query")]
+struct Args {
+    #[arg(short, long, help = "Output strings without quotes")]
+    raw_output: bool,
+
+    #[arg(short, long, help = "Output compact instead of pretty-printed JSON")]
+    compact_output: bool,
+
+    #[arg(help = "The jq-style filter to apply, e.g. .frame[0] (not glob-expanded) or '.players[] | .name'")]
+    filter: String,
+
+    #[arg(help = "The resource to query (defaults to the piped input)")]
+    path: Option<VirtualPathBuf>,
+}
+
+pub async fn invoke(args: &[String], input: Option<Value>, ctx: &mut Context) -> Result<Value> {
+    let args = Args::try_parse_from(args)?;
+    let filter = filter::parse(&args.filter)
+        .with_context(|| format!("Could not parse filter {}", args.filter))?;
+
+    let input = match (args.path, input) {
+        (Some(path), _) => ctx.lh.get(&ctx.cwd.join(path).as_lh_vec()).await?.payload,
+        // Piped text, e.g. from echo or a local file, is parsed as JSON if possible
+        (None, Some(Value::String(s))) => match serde_json::from_slice::<serde_json::Value>(s.as_bytes()) {
+            Ok(json_value) => to_value(json_value)?,
+            Err(_) => Value::String(s),
+        },
+        (None, Some(input)) => input,
+        (None, None) => bail!("Please specify a resource or pipe in input to query"),
+    };
+
+    let mut outputs = filter::evaluate(&filter, &input)?;
+
+    // Binary values (e.g. frames) cannot be represented in JSON without losing
+    // their type, so a single one is passed on as-is
+    if let [Value::Binary(_)] = outputs.as_slice() {
+        return Ok(outputs.remove(0));
+    }
+
+    let lines = outputs.iter()
+        .map(|output| Ok(match output {
+            Value::String(s) if args.raw_output => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+            output if args.compact_output => serde_json::to_string(&value::to_json(output))?,
+            output => serde_json::to_string_pretty(&value::to_json(output))?,
+        }))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Value::from(lines.join("\n")))
+}
@@ -10,7 +10,7 @@ struct Args {
     paths: Vec<VirtualPathBuf>,
 }
 
-pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
     let args = Args::try_parse_from(args)?;
     for path in args.paths {
         let path = ctx.cwd.join(path);
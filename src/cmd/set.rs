@@ -0,0 +1,13 @@
+use anyhow::Result;
+use lighthouse_client::protocol::Value;
+
+use crate::{context::Context, line::quote, value};
+
+pub async fn invoke(_args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let mut variables: Vec<(&String, &Value)> = ctx.variables.iter().collect();
+    variables.sort_by_key(|&(name, _)| name);
+    Ok(variables.into_iter()
+        .map(|(name, value)| format!("{}={}", name, quote(&value::to_text(value))))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -0,0 +1,32 @@
+use anyhow::{Context as _, Result};
+use clap::Parser;
+use lighthouse_client::protocol::Value;
+use tokio::fs;
+
+use crate::{context::Context, line};
+
+#[derive(Parser)]
+#[command(bin_name = "source")]
+struct Args {
+    #[arg(help = "The local script file to run")]
+    path: String,
+
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, help = "Positional arguments ($1, $2, ...) to pass to the script")]
+    args: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<Value>, ctx: &mut Context) -> Result<Value> {
+    let args = Args::try_parse_from(args)?;
+    let script = fs::read_to_string(&args.path).await
+        .with_context(|| format!("Could not read {}", args.path))?;
+    let body = line::parse(&script).map_err(|e| line::with_origin(e, &args.path))?;
+
+    // Like in POSIX shells, the script inherits the positional arguments unless
+    // new ones are given. Variables, functions and the cwd carry over either way.
+    let positional_args: Vec<String> = if args.args.is_empty() {
+        ctx.positional_args.clone()
+    } else {
+        ctx.positional_args.iter().take(1).cloned().chain(args.args).collect()
+    };
+    line::call_function(body, &positional_args, ctx).await
+}
@@ -15,7 +15,7 @@ struct Args {
     path: VirtualPathBuf,
 }
 
-pub async fn invoke(args: &[String], ctx: &mut Context) -> Result<String> {
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
     let args = Args::try_parse_from(args)?;
     let path = ctx.cwd.join(args.path);
 
@@ -67,20 +67,12 @@ fn write_tree(f: &mut fmt::Formatter<'_>, name: &str, tree: Option<&DirectoryTre
     Ok(())
 }
 
+#[derive(Default)]
 struct Stats {
     directory_count: usize,
     resource_count: usize,
 }
 
-impl Default for Stats {
-    fn default() -> Self {
-        Self {
-            directory_count: 0,
-            resource_count: 0,
-        }
-    }
-}
-
 impl Add for Stats {
     type Output = Stats;
 
@@ -94,8 +86,8 @@ impl Add for Stats {
 
 impl From<&DirectoryTree> for Stats {
     fn from(tree: &DirectoryTree) -> Self {
-        let mut aggregate = tree.entries.iter()
-            .map(|(_, child)| {
+        let mut aggregate = tree.entries.values()
+            .map(|child| {
                 if let Some(child) = child {
                     Self::from(child)
                 } else {
@@ -0,0 +1,27 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::context::Context;
+
+#[derive(Parser)]
+#[command(bin_name = "unalias")]
+struct Args {
+    #[arg(short, help = "Remove all aliases")]
+    all: bool,
+
+    #[arg(required_unless_present = "all", help = "The aliases to remove")]
+    names: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+    if args.all {
+        ctx.aliases.clear();
+    }
+    for name in args.names {
+        if ctx.aliases.remove(&name).is_none() {
+            bail!("unalias: {}: not found", name);
+        }
+    }
+    Ok(String::new())
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::context::Context;
+
+#[derive(Parser)]
+#[command(bin_name = "unset")]
+struct Args {
+    #[arg(short, long, help = "Remove functions instead of variables")]
+    functions: bool,
+
+    #[arg(required = true, help = "The names to remove")]
+    names: Vec<String>,
+}
+
+pub async fn invoke(args: &[String], _input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+    // Like in POSIX shells, unsetting a name that is not set is not an error
+    for name in args.names {
+        if args.functions {
+            ctx.functions.remove(&name);
+        } else {
+            ctx.variables.remove(&name);
+            ctx.exported.remove(&name);
+        }
+    }
+    Ok(String::new())
+}
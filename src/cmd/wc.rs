@@ -0,0 +1,48 @@
+use anyhow::Result;
+use clap::Parser;
+use lighthouse_client::protocol::Value;
+
+use crate::{context::Context, path::VirtualPathBuf};
+
+#[derive(Parser)]
+#[command(bin_name = "wc")]
+struct Args {
+    #[arg(short, long, action, help = "Print the line count")]
+    lines: bool,
+
+    #[arg(short, long, action, help = "Print the word count")]
+    words: bool,
+
+    #[arg(short = 'c', long, action, help = "Print the byte count")]
+    bytes: bool,
+
+    #[arg(help = "The resource to count (defaults to the piped input)")]
+    path: Option<VirtualPathBuf>,
+}
+
+pub async fn invoke(args: &[String], input: Option<String>, ctx: &mut Context) -> Result<String> {
+    let args = Args::try_parse_from(args)?;
+    let text = match (args.path, input) {
+        (Some(path), _) => {
+            let path = ctx.cwd.join(path);
+            let result: Value = ctx.lh.get(&path.as_lh_vec()).await?.payload;
+            format!("{}", result)
+        },
+        (None, Some(input)) => input,
+        (None, None) => String::new(),
+    };
+
+    let all = !args.lines && !args.words && !args.bytes;
+    let mut counts = Vec::new();
+    if all || args.lines {
+        counts.push(text.lines().count());
+    }
+    if all || args.words {
+        counts.push(text.split_whitespace().count());
+    }
+    if all || args.bytes {
+        counts.push(text.len());
+    }
+
+    Ok(counts.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" "))
+}
@@ -1,13 +1,35 @@
-use std::collections::HashMap;
+use std::{collections::{BTreeMap, HashMap, HashSet}, env};
 
-use lighthouse_client::{Lighthouse, TokioWebSocket};
+use lighthouse_client::{protocol::Value, Lighthouse, TokioWebSocket};
 
-use crate::path::VirtualPathBuf;
+use crate::{line::StatementList, path::VirtualPathBuf, status::ExitStatus, value};
 
 pub struct Context {
     pub lh: Lighthouse<TokioWebSocket>,
     pub cwd: VirtualPathBuf,
-    pub variables: HashMap<String, String>,
+    /// The shell variables, which may hold structured (or binary) values.
+    pub variables: HashMap<String, Value>,
+    /// The names of shell variables marked with `export`.
+    pub exported: HashSet<String>,
+    pub status: ExitStatus,
+    pub positional_args: Vec<String>,
+    pub functions: HashMap<String, StatementList>,
+    /// The number of function calls (and sourced scripts) currently running.
+    pub call_depth: usize,
+    pub aliases: HashMap<String, String>,
+    /// The aliases currently being expanded, which are not expanded again.
+    pub expanding_aliases: Vec<String>,
+    pub history: Vec<String>,
     pub username: String,
     pub host: String,
 }
+
+impl Context {
+    /// The environment passed on to child processes, i.e. the process
+    /// environment along with the exported shell variables.
+    pub fn environment(&self) -> BTreeMap<String, String> {
+        let exported = self.exported.iter()
+            .filter_map(|name| Some((name.clone(), value::to_text(self.variables.get(name)?))));
+        env::vars().chain(exported).collect()
+    }
+}
@@ -0,0 +1,815 @@
+use std::cmp::Ordering;
+
+use anyhow::{bail, Context as _, Result};
+use lighthouse_client::protocol::Value;
+
+use crate::value::{self, type_name};
+
+/// A jq-style filter, e.g. `.players[] | .name`, which produces any number of
+/// outputs for each input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// The input itself (`.`).
+    Identity,
+    Literal(Value),
+    /// Accesses the field (`.foo`) or element (`.[0]`) given by the index.
+    Index { target: Box<Filter>, index: Box<Filter> },
+    /// Slices an array, string or binary value (`.[1:3]`).
+    Slice { target: Box<Filter>, start: Option<Box<Filter>>, end: Option<Box<Filter>> },
+    /// Iterates over the elements of an array or the values of an object (`.[]`).
+    Iterate(Box<Filter>),
+    /// Suppresses the errors of the filter (`f?`).
+    Try(Box<Filter>),
+    Pipe(Box<Filter>, Box<Filter>),
+    Comma(Box<Filter>, Box<Filter>),
+    Binary { op: BinaryOperator, lhs: Box<Filter>, rhs: Box<Filter> },
+    Negate(Box<Filter>),
+    /// Collects the outputs into an array (`[f]`).
+    Array(Option<Box<Filter>>),
+    /// Constructs an object (`{name: .x}`).
+    Object(Vec<(Filter, Filter)>),
+    /// Calls a builtin function, e.g. `length` or `select(.x > 1)`.
+    Call { name: String, args: Vec<Filter> },
+}
+
+impl Filter {
+    /// The keys and indices of a plain path like `.config.speed` or `.frame[0]`,
+    /// if the filter is one.
+    pub fn as_path(&self) -> Option<Vec<Value>> {
+        match self {
+            Filter::Identity => Some(Vec::new()),
+            Filter::Index { target, index } => match index.as_ref() {
+                Filter::Literal(key) => {
+                    let mut path = target.as_path()?;
+                    path.push(key.clone());
+                    Some(path)
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    /// Outputs the left-hand side unless it is null or false (`//`).
+    Alternative,
+}
+
+/// The binary operators with their precedence, from loosest to tightest
+/// binding, like in jq.
+const BINARY_OPERATORS: [(&str, BinaryOperator, u8); 14] = [
+    ("//", BinaryOperator::Alternative, 1),
+    ("or", BinaryOperator::Or, 2),
+    ("and", BinaryOperator::And, 3),
+    ("==", BinaryOperator::Eq, 4),
+    ("!=", BinaryOperator::Ne, 4),
+    ("<", BinaryOperator::Lt, 4),
+    ("<=", BinaryOperator::Le, 4),
+    (">", BinaryOperator::Gt, 4),
+    (">=", BinaryOperator::Ge, 4),
+    ("+", BinaryOperator::Add, 5),
+    ("-", BinaryOperator::Sub, 5),
+    ("*", BinaryOperator::Mul, 6),
+    ("/", BinaryOperator::Div, 6),
+    ("%", BinaryOperator::Rem, 6),
+];
+
+/// The symbols, longest first so they are tokenized greedily.
+const SYMBOLS: [&str; 23] = [
+    "//", "==", "!=", "<=", ">=",
+    "|", ",", "[", "]", "(", ")", "{", "}", ":", ";", "?", "<", ">", "+", "-", "*", "/", "%",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    /// A field access, e.g. `.foo`.
+    Field(String),
+    Literal(Value),
+    /// A keyword or function name.
+    Name(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '.' {
+            let len = 1 + rest[1..].find(|c: char| !is_name_char(c)).unwrap_or(rest.len() - 1);
+            if len > 1 && !rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Field(rest[1..len].to_owned()));
+                len
+            } else {
+                tokens.push(Token::Dot);
+                1
+            }
+        } else if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            let number = &rest[..len];
+            let value = match number.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => Value::from(number.parse::<f64>().with_context(|| format!("Invalid number in filter: {}", number))?),
+            };
+            tokens.push(Token::Literal(value));
+            len
+        } else if c == '"' {
+            let (string, len) = tokenize_string(rest)?;
+            tokens.push(Token::Literal(Value::from(string)));
+            len
+        } else if is_name_char(c) {
+            let len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_owned()));
+            len
+        } else if let Some(symbol) = SYMBOLS.into_iter().find(|s| rest.starts_with(s)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            bail!("Unexpected {} in filter", c);
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Reads a JSON-style string literal, returning it along with its length in
+/// the source (including the quotes).
+fn tokenize_string(text: &str) -> Result<(String, usize)> {
+    let mut string = String::new();
+    let mut it = text.char_indices().skip(1);
+    while let Some((i, c)) = it.next() {
+        match c {
+            '"' => return Ok((string, i + 1)),
+            '\\' => match it.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, 'r')) => string.push('\r'),
+                Some((_, c @ ('"' | '\\' | '/'))) => string.push(c),
+                Some((_, c)) => bail!("Invalid escape \\{} in filter", c),
+                None => break,
+            },
+            c => string.push(c),
+        }
+    }
+    bail!("Unexpectedly reached end of string in filter")
+}
+
+/// Parses a filter.
+pub fn parse(text: &str) -> Result<Filter> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let filter = parser.parse_pipe()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {:?} in filter", token);
+    }
+    Ok(filter)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the symbol if it is next.
+    fn eat(&mut self, symbol: &str) -> bool {
+        let is_next = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if is_next {
+            self.pos += 1;
+        }
+        is_next
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if !self.eat(symbol) {
+            match self.peek() {
+                Some(token) => bail!("Expected {} in filter, but got {:?}", symbol, token),
+                None => bail!("Expected {} in filter, but reached the end", symbol),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a pipe, which binds loosest and is right-associative.
+    fn parse_pipe(&mut self) -> Result<Filter> {
+        let lhs = self.parse_comma()?;
+        if self.eat("|") {
+            return Ok(Filter::Pipe(Box::new(lhs), Box::new(self.parse_pipe()?)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_comma(&mut self) -> Result<Filter> {
+        let mut lhs = self.parse_binary(0)?;
+        while self.eat(",") {
+            lhs = Filter::Comma(Box::new(lhs), Box::new(self.parse_binary(0)?));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses binary operations binding tighter than the given precedence
+    /// using precedence climbing.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Filter> {
+        let mut lhs = self.parse_postfix()?;
+        loop {
+            let symbol = match self.peek() {
+                Some(Token::Symbol(s)) => *s,
+                Some(Token::Name(name)) => name.as_str(),
+                _ => break,
+            };
+            let Some((_, op, precedence)) = BINARY_OPERATORS.into_iter().find(|(s, _, _)| *s == symbol) else {
+                break;
+            };
+            if precedence <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(precedence)?;
+            lhs = Filter::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a term followed by accesses, e.g. `.players[0].name?`.
+    fn parse_postfix(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_term()?;
+        loop {
+            filter = match self.peek() {
+                Some(Token::Field(name)) => {
+                    let index = Filter::Literal(Value::from(name.as_str()));
+                    self.pos += 1;
+                    Filter::Index { target: Box::new(filter), index: Box::new(index) }
+                },
+                Some(Token::Dot) if matches!(self.tokens.get(self.pos + 1), Some(Token::Literal(Value::String(_)))) => {
+                    self.pos += 1;
+                    let index = self.parse_term()?;
+                    Filter::Index { target: Box::new(filter), index: Box::new(index) }
+                },
+                Some(Token::Dot) if matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol("["))) => {
+                    self.pos += 1;
+                    filter
+                },
+                Some(Token::Symbol("[")) => {
+                    self.pos += 1;
+                    self.parse_brackets(filter)?
+                },
+                Some(Token::Symbol("?")) => {
+                    self.pos += 1;
+                    Filter::Try(Box::new(filter))
+                },
+                _ => break,
+            };
+        }
+        Ok(filter)
+    }
+
+    /// Parses an index, slice or iteration, assuming that the opening bracket
+    /// has already been consumed.
+    fn parse_brackets(&mut self, target: Filter) -> Result<Filter> {
+        let target = Box::new(target);
+        if self.eat("]") {
+            return Ok(Filter::Iterate(target));
+        }
+        let start = if self.eat(":") { None } else { Some(Box::new(self.parse_pipe()?)) };
+        let filter = match start {
+            Some(index) if !self.eat(":") => Filter::Index { target, index },
+            start => {
+                let end = if matches!(self.peek(), Some(Token::Symbol("]"))) { None } else { Some(Box::new(self.parse_pipe()?)) };
+                Filter::Slice { target, start, end }
+            },
+        };
+        self.expect("]")?;
+        Ok(filter)
+    }
+
+    fn parse_term(&mut self) -> Result<Filter> {
+        match self.next().cloned() {
+            Some(Token::Dot) => match self.peek() {
+                Some(Token::Symbol("[")) => {
+                    self.pos += 1;
+                    self.parse_brackets(Filter::Identity)
+                },
+                Some(Token::Literal(Value::String(_))) => {
+                    let index = self.parse_term()?;
+                    Ok(Filter::Index { target: Box::new(Filter::Identity), index: Box::new(index) })
+                },
+                _ => Ok(Filter::Identity),
+            },
+            Some(Token::Field(name)) => Ok(Filter::Index {
+                target: Box::new(Filter::Identity),
+                index: Box::new(Filter::Literal(Value::from(name))),
+            }),
+            Some(Token::Literal(value)) => Ok(Filter::Literal(value)),
+            Some(Token::Name(name)) => match name.as_str() {
+                "true" => Ok(Filter::Literal(Value::from(true))),
+                "false" => Ok(Filter::Literal(Value::from(false))),
+                "null" => Ok(Filter::Literal(Value::Nil)),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat("(") {
+                        args.push(self.parse_pipe()?);
+                        while self.eat(";") {
+                            args.push(self.parse_pipe()?);
+                        }
+                        self.expect(")")?;
+                    }
+                    Ok(Filter::Call { name, args })
+                },
+            },
+            Some(Token::Symbol("(")) => {
+                let inner = self.parse_pipe()?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            Some(Token::Symbol("[")) => {
+                if self.eat("]") {
+                    return Ok(Filter::Array(None));
+                }
+                let inner = self.parse_pipe()?;
+                self.expect("]")?;
+                Ok(Filter::Array(Some(Box::new(inner))))
+            },
+            Some(Token::Symbol("{")) => self.parse_object(),
+            Some(Token::Symbol("-")) => Ok(Filter::Negate(Box::new(self.parse_postfix()?))),
+            Some(Token::Symbol(symbol)) => bail!("Unexpected {} in filter", symbol),
+            None => bail!("Expected filter, but reached the end"),
+        }
+    }
+
+    /// Parses an object construction, assuming that the opening brace has
+    /// already been consumed. Like in jq, `{name}` is short for `{name: .name}`.
+    fn parse_object(&mut self) -> Result<Filter> {
+        let mut entries = Vec::new();
+        while !self.eat("}") {
+            if !entries.is_empty() {
+                self.expect(",")?;
+            }
+            let key = match self.next().cloned() {
+                Some(Token::Name(name)) => Filter::Literal(Value::from(name)),
+                Some(Token::Literal(key @ Value::String(_))) => Filter::Literal(key),
+                Some(Token::Symbol("(")) => {
+                    let key = self.parse_pipe()?;
+                    self.expect(")")?;
+                    key
+                },
+                Some(token) => bail!("Unexpected {:?} in object key", token),
+                None => bail!("Expected object key, but reached the end"),
+            };
+            let value = if self.eat(":") {
+                self.parse_binary(0)?
+            } else {
+                Filter::Index { target: Box::new(Filter::Identity), index: Box::new(key.clone()) }
+            };
+            entries.push((key, value));
+        }
+        Ok(Filter::Object(entries))
+    }
+}
+
+/// Evaluates the filter against the input, returning all outputs in order.
+pub fn evaluate(filter: &Filter, input: &Value) -> Result<Vec<Value>> {
+    Ok(match filter {
+        Filter::Identity => vec![input.clone()],
+        Filter::Literal(value) => vec![value.clone()],
+        Filter::Index { target, index } => {
+            let indices = evaluate(index, input)?;
+            let mut outputs = Vec::new();
+            for target in evaluate(target, input)? {
+                for index in &indices {
+                    outputs.push(index_value(&target, index)?);
+                }
+            }
+            outputs
+        },
+        Filter::Slice { target, start, end } => {
+            let bound = |bound: &Option<Box<Filter>>| -> Result<Option<i64>> {
+                let Some(bound) = bound else {
+                    return Ok(None);
+                };
+                match evaluate(bound, input)?.as_slice() {
+                    [bound] if bound.is_nil() => Ok(None),
+                    [bound] => Ok(Some(bound.as_f64().context("Slice indices must be numbers")? as i64)),
+                    _ => bail!("Slice indices must be single values"),
+                }
+            };
+            let (start, end) = (bound(start)?, bound(end)?);
+            evaluate(target, input)?.iter()
+                .map(|target| slice_value(target, start, end))
+                .collect::<Result<_>>()?
+        },
+        Filter::Iterate(target) => {
+            let mut outputs = Vec::new();
+            for target in evaluate(target, input)? {
+                outputs.extend(iterate_value(&target)?);
+            }
+            outputs
+        },
+        Filter::Try(inner) => evaluate(inner, input).unwrap_or_default(),
+        Filter::Pipe(lhs, rhs) => {
+            let mut outputs = Vec::new();
+            for value in evaluate(lhs, input)? {
+                outputs.extend(evaluate(rhs, &value)?);
+            }
+            outputs
+        },
+        Filter::Comma(lhs, rhs) => {
+            let mut outputs = evaluate(lhs, input)?;
+            outputs.extend(evaluate(rhs, input)?);
+            outputs
+        },
+        Filter::Binary { op: BinaryOperator::Alternative, lhs, rhs } => {
+            let outputs: Vec<Value> = evaluate(lhs, input).unwrap_or_default().into_iter().filter(is_truthy).collect();
+            if outputs.is_empty() { evaluate(rhs, input)? } else { outputs }
+        },
+        Filter::Binary { op: op @ (BinaryOperator::And | BinaryOperator::Or), lhs, rhs } => {
+            let mut outputs = Vec::new();
+            for lhs in evaluate(lhs, input)? {
+                match (op, is_truthy(&lhs)) {
+                    (BinaryOperator::And, false) => outputs.push(Value::from(false)),
+                    (BinaryOperator::Or, true) => outputs.push(Value::from(true)),
+                    _ => outputs.extend(evaluate(rhs, input)?.iter().map(|rhs| Value::from(is_truthy(rhs)))),
+                }
+            }
+            outputs
+        },
+        Filter::Binary { op, lhs, rhs } => {
+            // Like in jq, the right-hand side is iterated in the outer loop
+            let lhs = evaluate(lhs, input)?;
+            let mut outputs = Vec::new();
+            for rhs in evaluate(rhs, input)? {
+                for lhs in &lhs {
+                    outputs.push(apply_binary(*op, lhs, &rhs)?);
+                }
+            }
+            outputs
+        },
+        Filter::Negate(inner) => evaluate(inner, input)?.iter()
+            .map(|value| apply_binary(BinaryOperator::Sub, &Value::from(0), value))
+            .collect::<Result<_>>()?,
+        Filter::Array(None) => vec![Value::Array(Vec::new())],
+        Filter::Array(Some(inner)) => vec![Value::Array(evaluate(inner, input)?)],
+        Filter::Object(entries) => {
+            let mut objects = vec![Vec::new()];
+            for (key, value) in entries {
+                let keys = evaluate(key, input)?;
+                let values = evaluate(value, input)?;
+                let mut extended = Vec::new();
+                for object in &objects {
+                    for key in &keys {
+                        if !key.is_str() {
+                            bail!("Object keys must be strings, but got {}", type_name(key));
+                        }
+                        for value in &values {
+                            let mut object: Vec<(Value, Value)> = object.clone();
+                            object.retain(|(k, _)| k != key);
+                            object.push((key.clone(), value.clone()));
+                            extended.push(object);
+                        }
+                    }
+                }
+                objects = extended;
+            }
+            objects.into_iter().map(Value::Map).collect()
+        },
+        Filter::Call { name, args } => call(name, args, input)?,
+    })
+}
+
+fn call(name: &str, args: &[Filter], input: &Value) -> Result<Vec<Value>> {
+    Ok(match (name, args) {
+        ("empty", []) => Vec::new(),
+        ("not", []) => vec![Value::from(!is_truthy(input))],
+        ("type", []) => vec![Value::from(type_name(input))],
+        ("length", []) => vec![match input {
+            Value::Nil => Value::from(0),
+            Value::String(s) => Value::from(String::from_utf8_lossy(s.as_bytes()).chars().count()),
+            Value::Binary(bytes) => Value::from(bytes.len()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Map(entries) => Value::from(entries.len()),
+            value if value.is_number() => apply_binary(BinaryOperator::Mul, value, &Value::from(value.as_f64().unwrap().signum() as i64))?,
+            value => bail!("{} has no length", type_name(value)),
+        }],
+        ("keys", []) => vec![match input {
+            Value::Map(entries) => {
+                let mut keys: Vec<Value> = entries.iter().map(|(k, _)| k.clone()).collect();
+                keys.sort_by(compare);
+                Value::Array(keys)
+            },
+            Value::Array(items) => Value::Array((0..items.len()).map(Value::from).collect()),
+            value => bail!("{} has no keys", type_name(value)),
+        }],
+        ("has", [key]) => evaluate(key, input)?.iter()
+            .map(|key| Ok(Value::from(match (input, key) {
+                (Value::Map(entries), key) => entries.iter().any(|(k, _)| k == key),
+                (Value::Array(items), index) => index.as_f64().is_some_and(|i| i >= 0.0 && (i as usize) < items.len()),
+                (value, key) => bail!("Cannot check whether {} has a {} key", type_name(value), type_name(key)),
+            })))
+            .collect::<Result<_>>()?,
+        ("select", [condition]) => evaluate(condition, input)?.iter()
+            .filter(|value| is_truthy(value))
+            .map(|_| input.clone())
+            .collect(),
+        ("map", [f]) => {
+            let mut outputs = Vec::new();
+            for value in iterate_value(input)? {
+                outputs.extend(evaluate(f, &value)?);
+            }
+            vec![Value::Array(outputs)]
+        },
+        ("add", []) => {
+            let mut sum = Value::Nil;
+            for value in iterate_value(input)? {
+                sum = apply_binary(BinaryOperator::Add, &sum, &value)?;
+            }
+            vec![sum]
+        },
+        ("first", []) => vec![index_value(input, &Value::from(0))?],
+        ("last", []) => vec![index_value(input, &Value::from(-1))?],
+        ("reverse", []) => vec![match input {
+            Value::Nil => Value::Array(Vec::new()),
+            Value::String(s) => Value::from(String::from_utf8_lossy(s.as_bytes()).chars().rev().collect::<String>()),
+            value => Value::Array(iterate_value(value)?.into_iter().rev().collect()),
+        }],
+        ("sort", []) => {
+            let Value::Array(items) = input else {
+                bail!("{} cannot be sorted, as it is not an array", type_name(input));
+            };
+            let mut items = items.clone();
+            items.sort_by(compare);
+            vec![Value::Array(items)]
+        },
+        ("tostring", []) => vec![match input {
+            Value::String(_) => input.clone(),
+            value => Value::from(value::to_json(value).to_string()),
+        }],
+        ("tonumber", []) => vec![match input {
+            value if value.is_number() => value.clone(),
+            Value::String(s) => {
+                let text = String::from_utf8_lossy(s.as_bytes());
+                match text.parse::<i64>() {
+                    Ok(n) => Value::from(n),
+                    Err(_) => Value::from(text.parse::<f64>().with_context(|| format!("Cannot parse {:?} as number", text))?),
+                }
+            },
+            value => bail!("{} cannot be parsed as a number", type_name(value)),
+        }],
+        (name, args) => bail!("Unknown function {}/{}", name, args.len()),
+    })
+}
+
+/// Whether the value counts as true, i.e. is neither null nor false.
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Nil | Value::Boolean(false))
+}
+
+fn index_value(target: &Value, index: &Value) -> Result<Value> {
+    let element = |len: usize| -> Option<usize> {
+        let i = index.as_f64()? as i64;
+        let i = if i < 0 { i + len as i64 } else { i };
+        (0..len as i64).contains(&i).then_some(i as usize)
+    };
+    Ok(match (target, index) {
+        (Value::Nil, _) => Value::Nil,
+        (Value::Map(entries), key @ Value::String(_)) => entries.iter()
+            .find(|(k, _)| k == key)
+            .map_or(Value::Nil, |(_, v)| v.clone()),
+        (Value::Array(items), index) if index.is_number() => element(items.len()).map_or(Value::Nil, |i| items[i].clone()),
+        (Value::Binary(bytes), index) if index.is_number() => element(bytes.len()).map_or(Value::Nil, |i| Value::from(bytes[i])),
+        (target, index) => bail!("Cannot index {} with {}", type_name(target), type_name(index)),
+    })
+}
+
+fn slice_value(target: &Value, start: Option<i64>, end: Option<i64>) -> Result<Value> {
+    let range = |len: usize| {
+        let clamp = |i: i64| (if i < 0 { i + len as i64 } else { i }).clamp(0, len as i64) as usize;
+        let start = start.map_or(0, clamp);
+        let end = end.map_or(len, clamp);
+        start..end.max(start)
+    };
+    Ok(match target {
+        Value::Nil => Value::Nil,
+        Value::Array(items) => Value::Array(items[range(items.len())].to_vec()),
+        Value::Binary(bytes) => Value::Binary(bytes[range(bytes.len())].to_vec()),
+        Value::String(s) => {
+            let chars: Vec<char> = String::from_utf8_lossy(s.as_bytes()).chars().collect();
+            Value::from(chars[range(chars.len())].iter().collect::<String>())
+        },
+        target => bail!("Cannot slice {}", type_name(target)),
+    })
+}
+
+fn iterate_value(value: &Value) -> Result<Vec<Value>> {
+    Ok(match value {
+        Value::Array(items) => items.clone(),
+        Value::Map(entries) => entries.iter().map(|(_, v)| v.clone()).collect(),
+        Value::Binary(bytes) => bytes.iter().map(|&b| Value::from(b)).collect(),
+        value => bail!("Cannot iterate over {}", type_name(value)),
+    })
+}
+
+/// Orders values like jq, i.e. null < booleans < numbers < strings < arrays <
+/// objects, comparing values of the same type by their contents.
+fn compare(lhs: &Value, rhs: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Nil => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) | Value::F32(_) | Value::F64(_) => 2,
+        Value::String(_) => 3,
+        Value::Binary(_) => 4,
+        Value::Array(_) => 5,
+        Value::Map(_) => 6,
+        Value::Ext(_, _) => 7,
+    };
+    match (lhs, rhs) {
+        (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
+        (l, r) if l.is_number() && r.is_number() => match (l.as_i64(), r.as_i64()) {
+            (Some(l), Some(r)) => l.cmp(&r),
+            _ => l.as_f64().unwrap().total_cmp(&r.as_f64().unwrap()),
+        },
+        (Value::String(l), Value::String(r)) => l.as_bytes().cmp(r.as_bytes()),
+        (Value::Binary(l), Value::Binary(r)) => l.cmp(r),
+        (Value::Array(l), Value::Array(r)) => l.iter().zip(r)
+            .map(|(l, r)| compare(l, r))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| l.len().cmp(&r.len())),
+        (Value::Map(l), Value::Map(r)) => {
+            let sorted = |entries: &[(Value, Value)]| {
+                let mut entries = entries.to_vec();
+                entries.sort_by(|(k1, _), (k2, _)| compare(k1, k2));
+                entries
+            };
+            let (l, r) = (sorted(l), sorted(r));
+            let keys = |entries: &[(Value, Value)]| Value::Array(entries.iter().map(|(k, _)| k.clone()).collect());
+            let values = |entries: &[(Value, Value)]| Value::Array(entries.iter().map(|(_, v)| v.clone()).collect());
+            compare(&keys(&l), &keys(&r)).then_with(|| compare(&values(&l), &values(&r)))
+        },
+        (l, r) => rank(l).cmp(&rank(r)),
+    }
+}
+
+fn apply_binary(op: BinaryOperator, lhs: &Value, rhs: &Value) -> Result<Value> {
+    let ordering = || compare(lhs, rhs);
+    Ok(match op {
+        BinaryOperator::Eq => Value::from(ordering().is_eq()),
+        BinaryOperator::Ne => Value::from(ordering().is_ne()),
+        BinaryOperator::Lt => Value::from(ordering().is_lt()),
+        BinaryOperator::Le => Value::from(ordering().is_le()),
+        BinaryOperator::Gt => Value::from(ordering().is_gt()),
+        BinaryOperator::Ge => Value::from(ordering().is_ge()),
+        _ if lhs.is_number() && rhs.is_number() => apply_arithmetic(op, lhs, rhs)?,
+        BinaryOperator::Add => match (lhs, rhs) {
+            (Value::Nil, value) | (value, Value::Nil) => value.clone(),
+            (Value::String(l), Value::String(r)) => Value::from(String::from_utf8_lossy(&[l.as_bytes(), r.as_bytes()].concat()).into_owned()),
+            (Value::Array(l), Value::Array(r)) => Value::Array([l.as_slice(), r.as_slice()].concat()),
+            (Value::Binary(l), Value::Binary(r)) => Value::Binary([l.as_slice(), r.as_slice()].concat()),
+            (Value::Map(l), Value::Map(r)) => {
+                let mut merged: Vec<(Value, Value)> = l.iter().filter(|(k, _)| !r.iter().any(|(rk, _)| rk == k)).cloned().collect();
+                merged.extend(r.iter().cloned());
+                Value::Map(merged)
+            },
+            (l, r) => bail!("{} and {} cannot be added", type_name(l), type_name(r)),
+        },
+        BinaryOperator::Sub => match (lhs, rhs) {
+            (Value::Array(l), Value::Array(r)) => Value::Array(l.iter().filter(|v| !r.iter().any(|w| compare(v, w).is_eq())).cloned().collect()),
+            (l, r) => bail!("{} and {} cannot be subtracted", type_name(l), type_name(r)),
+        },
+        _ => bail!("{} and {} cannot be combined with {:?}", type_name(lhs), type_name(rhs), op),
+    })
+}
+
+/// Applies the arithmetic operator to numbers, keeping integers where possible.
+fn apply_arithmetic(op: BinaryOperator, lhs: &Value, rhs: &Value) -> Result<Value> {
+    if let (Some(l), Some(r)) = (lhs.as_i64(), rhs.as_i64()) {
+        let result = match op {
+            BinaryOperator::Add => l.checked_add(r),
+            BinaryOperator::Sub => l.checked_sub(r),
+            BinaryOperator::Mul => l.checked_mul(r),
+            BinaryOperator::Div | BinaryOperator::Rem if r == 0 => bail!("{} cannot be divided because the divisor is zero", l),
+            // Like the other operators, these fall back to floats on overflow (i.e. for i64::MIN / -1)
+            BinaryOperator::Div => l.checked_rem(r).filter(|&rem| rem == 0).and_then(|_| l.checked_div(r)),
+            BinaryOperator::Rem => l.checked_rem(r),
+            _ => None,
+        };
+        if let Some(result) = result {
+            return Ok(Value::from(result));
+        }
+    }
+    let (l, r) = (lhs.as_f64().unwrap(), rhs.as_f64().unwrap());
+    Ok(Value::from(match op {
+        BinaryOperator::Add => l + r,
+        BinaryOperator::Sub => l - r,
+        BinaryOperator::Mul => l * r,
+        BinaryOperator::Div if r == 0.0 => bail!("{} cannot be divided because the divisor is zero", l),
+        BinaryOperator::Div => l / r,
+        BinaryOperator::Rem => l % r,
+        _ => bail!("Unsupported arithmetic operator {:?}", op),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use lighthouse_client::protocol::{to_value, Value};
+    use serde_json::json;
+
+    use super::{evaluate, parse};
+
+    fn query(filter: &str, input: serde_json::Value) -> Vec<Value> {
+        evaluate(&parse(filter).unwrap(), &to_value(input).unwrap()).unwrap()
+    }
+
+    fn values<const N: usize>(values: [serde_json::Value; N]) -> Vec<Value> {
+        values.into_iter().map(|v| to_value(v).unwrap()).collect()
+    }
+
+    fn state() -> serde_json::Value {
+        json!({
+            "frame": [1, 2, 3, 4],
+            "players": [{"name": "a", "score": 3}, {"name": "b", "score": 7}],
+            "title": "Tetris",
+        })
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(query(".", json!(1)), values([json!(1)]));
+        assert_eq!(query(".title", state()), values([json!("Tetris")]));
+        assert_eq!(query(".frame[0]", state()), values([json!(1)]));
+        assert_eq!(query(".frame[-1]", state()), values([json!(4)]));
+        assert_eq!(query(".frame[1:3]", state()), values([json!([2, 3])]));
+        assert_eq!(query(".players[1].name", state()), values([json!("b")]));
+        assert_eq!(query(".[\"title\"]", state()), values([json!("Tetris")]));
+        assert_eq!(query(".missing.x", state()), values([json!(null)]));
+        assert_eq!(query(".players[] | .name", state()), values([json!("a"), json!("b")]));
+        assert_eq!(query(".players[].name", state()), values([json!("a"), json!("b")]));
+        assert_eq!(query(".title, .frame[0]", state()), values([json!("Tetris"), json!(1)]));
+        assert_eq!(query(".title[0]?", state()), values([]));
+        assert_eq!(evaluate(&parse(".[1:]").unwrap(), &Value::Binary(vec![7, 8])).unwrap(), vec![Value::Binary(vec![8])]);
+        assert_eq!(evaluate(&parse(".[]").unwrap(), &Value::Binary(vec![7, 8])).unwrap(), values([json!(7), json!(8)]));
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(query("keys", state()), values([json!(["frame", "players", "title"])]));
+        assert_eq!(query(".frame | length", state()), values([json!(4)]));
+        assert_eq!(query(".title | length", state()), values([json!(6)]));
+        assert_eq!(query(".players[] | select(.score > 5) | .name", state()), values([json!("b")]));
+        assert_eq!(query(".frame | map(. * 2)", state()), values([json!([2, 4, 6, 8])]));
+        assert_eq!(query("[.players[].score] | add", state()), values([json!(10)]));
+        assert_eq!(query("has(\"title\"), has(\"x\")", state()), values([json!(true), json!(false)]));
+        assert_eq!(query(".players | map(.name) | sort | reverse | first", state()), values([json!("b")]));
+        assert_eq!(query(".frame | type", state()), values([json!("array")]));
+        assert_eq!(query(".x // \"default\"", state()), values([json!("default")]));
+        assert!(evaluate(&parse("frobnicate").unwrap(), &Value::Nil).is_err());
+    }
+
+    #[test]
+    fn arithmetic_overflow() {
+        let input = json!({"min": i64::MIN});
+        assert_eq!(query(".min / -1", input.clone())[0].as_f64(), Some(-(i64::MIN as f64)));
+        assert_eq!(query(".min % -1", input.clone())[0].as_f64(), Some(0.0));
+        assert_eq!(query(".min / 2", input), values([json!(i64::MIN / 2)]));
+    }
+
+    #[test]
+    fn constructions() {
+        assert_eq!(query("[.frame[] | select(. % 2 == 0)]", state()), values([json!([2, 4])]));
+        assert_eq!(query("{best: .players[1].name, title}", state()), values([json!({"title": "Tetris", "best": "b"})]));
+        assert_eq!(query(".frame[0] + 1.5, -.frame[1], 7 / 2, \"a\" + \"b\"", state()), values([json!(2.5), json!(-2), json!(3.5), json!("ab")]));
+        assert_eq!(query(".frame[0] == 1 and (.title != \"x\" or false)", state()), values([json!(true)]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(".[").is_err());
+        assert!(parse(".a |").is_err());
+        assert!(parse("{a: }").is_err());
+        assert!(parse("\"abc").is_err());
+        assert!(parse(".a )").is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use lighthouse_client::{Lighthouse, TokioWebSocket};
+use rustyline::{completion::{Completer, Pair}, Context as LineContext};
+use tokio::{runtime::Handle, task};
+
+use crate::{cmd, context::Context, path::{VirtualPathBuf, SEPARATOR}};
+
+/// How long directory listings are cached for completion. Changes made by
+/// this shell invalidate the cache sooner, changes by other clients don't.
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Commands that may modify the resource tree and thus invalidate the cache.
+const MODIFYING_COMMANDS: [&str; 10] = ["cp", "ln", "mkdir", "mv", "patch", "rm", "rmdir", "source", "touch", "uln"];
+
+/// Characters that are escaped in completed paths, in addition to whitespace
+/// and the word break characters.
+const ESCAPED_CHARS: [char; 10] = ['\\', '\'', '"', '$', '`', '#', '~', '*', '?', '['];
+
+/// Characters that separate words, in addition to whitespace.
+const WORD_BREAK_CHARS: [char; 8] = [';', '|', '&', '<', '>', '=', '(', ')'];
+
+/// Keywords after which a command is expected.
+pub(super) const COMMAND_KEYWORDS: [&str; 7] = ["if", "then", "elif", "else", "while", "do", "{"];
+
+/// An entry of a directory listing.
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    is_directory: bool,
+}
+
+/// Completes command names and remote resource paths.
+pub struct ShellCompleter {
+    lh: Lighthouse<TokioWebSocket>,
+    cwd: VirtualPathBuf,
+    /// The names of user-defined functions and aliases.
+    user_commands: Vec<String>,
+    cache: Mutex<HashMap<VirtualPathBuf, (Instant, Vec<Entry>)>>,
+}
+
+impl ShellCompleter {
+    pub fn new(lh: Lighthouse<TokioWebSocket>) -> Self {
+        Self {
+            lh,
+            cwd: VirtualPathBuf::root(),
+            user_commands: Vec::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn refresh(&mut self, ctx: &Context) {
+        self.cwd = ctx.cwd.clone();
+        self.user_commands = ctx.functions.keys().chain(ctx.aliases.keys()).cloned().collect();
+    }
+
+    /// Invalidates the cached listings if the interpreted line may have
+    /// modified the resource tree.
+    pub fn invalidate_after(&mut self, line: &str) {
+        if may_modify_resources(line, |word| self.user_commands.iter().any(|c| c == word)) {
+            self.cache.get_mut().unwrap().clear();
+        }
+    }
+
+    /// Whether the name refers to a builtin command, a function or an alias.
+    pub fn is_command(&self, name: &str) -> bool {
+        cmd::COMMANDS.contains(&name) || self.user_commands.iter().any(|f| f == name)
+    }
+
+    fn complete_command(&self, prefix: &str) -> Vec<Pair> {
+        let mut names: Vec<&str> = cmd::COMMANDS.iter()
+            .copied()
+            .chain(self.user_commands.iter().map(|f| f.as_str()))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names.into_iter()
+            .map(|name| Pair { display: name.to_owned(), replacement: format!("{} ", name) })
+            .collect()
+    }
+
+    fn complete_path(&self, word: &str) -> Vec<Pair> {
+        let (dir, prefix) = match word.rfind(SEPARATOR) {
+            Some(i) => (&word[..=i], &word[(i + 1)..]),
+            None => ("", word),
+        };
+        let dir_path = self.cwd.join(VirtualPathBuf::from(if dir.is_empty() { "." } else { dir }));
+        let Some(entries) = self.list(&dir_path) else {
+            return Vec::new();
+        };
+        let mut pairs: Vec<Pair> = entries.into_iter()
+            .filter(|e| e.name.starts_with(prefix))
+            .map(|e| {
+                let suffix = if e.is_directory { SEPARATOR } else { "" };
+                Pair {
+                    display: format!("{}{}", e.name, suffix),
+                    replacement: format!("{}{}{}", escape(dir), escape(&e.name), suffix),
+                }
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.display.cmp(&b.display));
+        pairs
+    }
+
+    /// Whether the path refers to a directory (`Some(true)`) or a resource
+    /// (`Some(false)`) according to the cached listings. Unlike completion,
+    /// this never fetches listings, since it runs on every keystroke.
+    pub fn cached_is_directory(&self, path: &str) -> Option<bool> {
+        let path = self.cwd.join(VirtualPathBuf::from(path.trim_end_matches(SEPARATOR)));
+        if path.is_root() {
+            return Some(true);
+        }
+        let name = path.as_str_vec().last()?.to_string();
+        let cache = self.cache.lock().unwrap();
+        let (fetched, entries) = cache.get(&path.parent().to_owned())?;
+        if fetched.elapsed() >= CACHE_TTL {
+            return None;
+        }
+        entries.iter().find(|e| e.name == name).map(|e| e.is_directory)
+    }
+
+    /// Lists the directory, using the cache if possible.
+    fn list(&self, path: &VirtualPathBuf) -> Option<Vec<Entry>> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((fetched, entries)) = cache.get(path) {
+            if fetched.elapsed() < CACHE_TTL {
+                return Some(entries.clone());
+            }
+        }
+
+        // Completion is synchronous, so we block on the request. This is fine
+        // since the shell itself is blocked while reading the line anyway.
+        let lh_path = path.as_lh_vec();
+        let tree = task::block_in_place(|| Handle::current().block_on(async {
+            self.lh.list(&lh_path).await.ok()
+        }))?.payload;
+        let entries: Vec<Entry> = tree.entries.into_iter()
+            .map(|(name, contents)| Entry { name, is_directory: contents.is_some() })
+            .collect();
+
+        cache.insert(path.clone(), (Instant::now(), entries.clone()));
+        Some(entries)
+    }
+}
+
+impl Completer for ShellCompleter {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &LineContext<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = word_start(before);
+        let word = unescape(&before[start..]);
+
+        let candidates = if is_command_position(&before[..start]) {
+            self.complete_command(&word)
+        } else {
+            self.complete_path(&word)
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+/// Whether the character separates words when unescaped.
+fn is_word_break(c: char) -> bool {
+    c.is_whitespace() || WORD_BREAK_CHARS.contains(&c)
+}
+
+/// The byte offset of the word ending the text, skipping escaped word breaks
+/// such as the space in `my\ dir`.
+fn word_start(before: &str) -> usize {
+    let mut start = 0;
+    let mut it = before.char_indices();
+    while let Some((i, c)) = it.next() {
+        if c == '\\' {
+            it.next();
+        } else if is_word_break(c) {
+            start = i + c.len_utf8();
+        }
+    }
+    start
+}
+
+/// Escapes the characters of a completed name that the shell would otherwise
+/// interpret, e.g. `my dir` to `my\ dir`.
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        if is_word_break(c) || ESCAPED_CHARS.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the escapes from a partially typed word.
+fn unescape(word: &str) -> String {
+    let mut unescaped = String::new();
+    let mut it = word.chars();
+    while let Some(c) = it.next() {
+        unescaped.extend(if c == '\\' { it.next() } else { Some(c) });
+    }
+    unescaped
+}
+
+/// Whether interpreting the line may have modified the resource tree, i.e.
+/// whether it redirects to a resource or mentions a modifying command or a
+/// user-defined command (which may run either). This errs on the side of
+/// invalidating, e.g. for `echo rm`.
+fn may_modify_resources(line: &str, is_user_command: impl Fn(&str) -> bool) -> bool {
+    line.contains('>') || line.split(is_word_break)
+        .any(|word| MODIFYING_COMMANDS.contains(&word) || is_user_command(word))
+}
+
+/// Whether a word following the given text is in command position, i.e.
+/// names the command to invoke.
+fn is_command_position(before: &str) -> bool {
+    let before = before.trim_end();
+    before.is_empty()
+        || before.ends_with([';', '|', '&', '(', '\n'])
+        || before.split_whitespace().last().is_some_and(|w| COMMAND_KEYWORDS.contains(&w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, is_command_position, may_modify_resources, unescape, word_start};
+
+    #[test]
+    fn command_positions() {
+        assert!(is_command_position(""));
+        assert!(is_command_position("  "));
+        assert!(is_command_position("ls; "));
+        assert!(is_command_position("ls && "));
+        assert!(is_command_position("ls | "));
+        assert!(is_command_position("if "));
+        assert!(is_command_position("for x in a; do "));
+        assert!(!is_command_position("ls "));
+        assert!(!is_command_position("cat a | wc "));
+        assert!(!is_command_position("for "));
+    }
+
+    #[test]
+    fn escaped_names() {
+        assert_eq!(escape("frame0"), "frame0");
+        assert_eq!(escape("my dir"), "my\\ dir");
+        assert_eq!(escape("a&b(1)"), "a\\&b\\(1\\)");
+        assert_eq!(unescape(&escape("it's $x; *")), "it's $x; *");
+        assert_eq!(word_start("cat my\\ d"), 4);
+        assert_eq!(word_start("cat a b\\\\ c"), 10);
+        assert_eq!(word_start("ls|wc"), 3);
+    }
+
+    #[test]
+    fn modifying_lines() {
+        let no_user_commands = |_: &str| false;
+        assert!(may_modify_resources("mkdir -p frames", no_user_commands));
+        assert!(may_modify_resources("ls && rm a", no_user_commands));
+        assert!(may_modify_resources("echo 1 > a", no_user_commands));
+        assert!(may_modify_resources("setup", |word| word == "setup"));
+        assert!(!may_modify_resources("ls frames | wc -l", no_user_commands));
+        assert!(!may_modify_resources("cat form", no_user_commands));
+    }
+}
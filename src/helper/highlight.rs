@@ -0,0 +1,277 @@
+use std::ops::Range;
+
+use colored::Colorize;
+
+use crate::line::{lex_partial, Operator, SegmentKind, Token, KEYWORDS};
+
+use super::complete::COMMAND_KEYWORDS;
+
+/// The style of a highlighted span of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Command,
+    UnknownCommand,
+    Keyword,
+    Operator,
+    Quoted,
+    Variable,
+    Directory,
+    Resource,
+    Comment,
+    /// Input that could not be lexed (yet), e.g. an unclosed quote.
+    Incomplete,
+}
+
+impl Style {
+    fn paint(self, text: &str) -> String {
+        match self {
+            Style::Plain => text.to_owned(),
+            Style::Command => text.green().bold().to_string(),
+            Style::UnknownCommand => text.red().to_string(),
+            Style::Keyword => text.magenta().to_string(),
+            Style::Operator => text.bold().to_string(),
+            Style::Quoted => text.yellow().to_string(),
+            Style::Variable => text.cyan().to_string(),
+            Style::Directory => text.blue().to_string(),
+            Style::Resource => text.underline().to_string(),
+            Style::Comment => text.dimmed().to_string(),
+            Style::Incomplete => text.red().to_string(),
+        }
+    }
+}
+
+/// Highlights the line for display in the terminal. Words in command position
+/// are highlighted depending on whether `is_command` recognizes them, other
+/// words depending on whether `is_directory` knows them as a path to a
+/// directory (`Some(true)`) or a resource (`Some(false)`).
+pub fn highlight(line: &str, is_command: impl Fn(&str) -> bool, is_directory: impl Fn(&str) -> Option<bool>) -> String {
+    spans(line, is_command, is_directory).into_iter()
+        .map(|(style, range)| style.paint(&line[range]))
+        .collect()
+}
+
+/// Splits the line into styled spans based on its tokens. Since the line is
+/// highlighted while it is being typed, the part that doesn't lex (yet) is
+/// styled as incomplete instead of failing.
+fn spans(line: &str, is_command: impl Fn(&str) -> bool, is_directory: impl Fn(&str) -> Option<bool>) -> Vec<(Style, Range<usize>)> {
+    let (tokens, incomplete_start) = lex_partial(line);
+    let end = incomplete_start.unwrap_or(line.len());
+    let mut spans = Vec::new();
+    let mut command_position = true;
+    let mut pending_heredocs = false;
+    let mut in_heredoc_body = false;
+    let mut last_end = 0;
+
+    for (i, (token, range)) in tokens.iter().enumerate() {
+        spans.extend(gap_spans(line, last_end..range.start, in_heredoc_body));
+        in_heredoc_body = false;
+        last_end = range.end;
+        let text = &line[range.clone()];
+        match token {
+            Token::Operator(op) => {
+                let style = if *op == Operator::Newline { Style::Plain } else { Style::Operator };
+                spans.push((style, range.clone()));
+                match op {
+                    Operator::Heredoc => pending_heredocs = true,
+                    Operator::Newline => in_heredoc_body = std::mem::take(&mut pending_heredocs),
+                    _ => {},
+                }
+                command_position = matches!(op, Operator::Semicolon | Operator::Newline | Operator::Pipe | Operator::And | Operator::Or | Operator::LeftParen | Operator::RightParen);
+            },
+            Token::String(segments) => {
+                // Only words that are neither quoted nor escaped can name commands
+                let is_bare = token.to_string() == text
+                    && segments.iter().all(|s| matches!(s.kind, SegmentKind::Literal | SegmentKind::Glob | SegmentKind::Brace));
+                let is_heredoc_delimiter = i > 0 && matches!(tokens[i - 1].0, Token::Operator(Operator::Heredoc));
+                let style = if is_heredoc_delimiter {
+                    // The token holds the body, which is styled separately
+                    Style::Plain
+                } else if command_position && is_bare {
+                    let is_assignment = matches!(tokens.get(i + 1), Some((Token::Operator(Operator::Assign), next)) if next.start == range.end);
+                    if KEYWORDS.contains(&text) {
+                        Style::Keyword
+                    } else if is_assignment {
+                        Style::Variable
+                    } else if is_command(text) {
+                        Style::Command
+                    } else {
+                        Style::UnknownCommand
+                    }
+                } else if text.starts_with(['\'', '"']) {
+                    Style::Quoted
+                } else if segments.iter().any(|s| matches!(s.kind, SegmentKind::Variable | SegmentKind::Parameter | SegmentKind::Command | SegmentKind::Arithmetic)) {
+                    Style::Variable
+                } else if segments.iter().all(|s| s.kind == SegmentKind::Literal) {
+                    match is_directory(&token.to_string()) {
+                        Some(true) => Style::Directory,
+                        Some(false) => Style::Resource,
+                        None => Style::Plain,
+                    }
+                } else {
+                    Style::Plain
+                };
+                spans.push((style, range.clone()));
+                command_position = command_position && COMMAND_KEYWORDS.contains(&text);
+            },
+        }
+    }
+
+    spans.extend(gap_spans(line, last_end..end, in_heredoc_body));
+    if end < line.len() {
+        spans.push((Style::Incomplete, end..line.len()));
+    }
+    merge(spans)
+}
+
+/// Styles the text between two tokens, which the lexer skipped since it
+/// consists of whitespace and comments (or is the body of a here-document).
+fn gap_spans(line: &str, range: Range<usize>, is_heredoc_body: bool) -> Vec<(Style, Range<usize>)> {
+    if range.is_empty() {
+        return Vec::new();
+    }
+    if is_heredoc_body {
+        return vec![(Style::Quoted, range)];
+    }
+    match line[range.clone()].find('#') {
+        Some(i) => vec![(Style::Plain, range.start..(range.start + i)), (Style::Comment, (range.start + i)..range.end)],
+        None => vec![(Style::Plain, range)],
+    }
+}
+
+/// Merges adjacent spans of the same style, dropping empty ones.
+fn merge(spans: Vec<(Style, Range<usize>)>) -> Vec<(Style, Range<usize>)> {
+    let mut merged: Vec<(Style, Range<usize>)> = Vec::new();
+    for (style, range) in spans.into_iter().filter(|(_, range)| !range.is_empty()) {
+        match merged.last_mut() {
+            Some((last_style, last_range)) if *last_style == style && last_range.end == range.start => {
+                last_range.end = range.end;
+            },
+            _ => merged.push((style, range)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spans, Style};
+
+    fn styles(line: &str) -> Vec<(Style, &str)> {
+        let is_directory = |path: &str| match path {
+            "frames" | "/frames" => Some(true),
+            "frames/frame0" => Some(false),
+            _ => None,
+        };
+        spans(line, |w| ["ls", "echo", "cat"].contains(&w), is_directory).into_iter()
+            .map(|(style, range)| (style, &line[range]))
+            .collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(styles(""), vec![]);
+        assert_eq!(styles("ls"), vec![(Style::Command, "ls")]);
+        assert_eq!(styles("lx"), vec![(Style::UnknownCommand, "lx")]);
+        assert_eq!(styles("ls a"), vec![(Style::Command, "ls"), (Style::Plain, " a")]);
+        assert_eq!(styles("ls | cat"), vec![
+            (Style::Command, "ls"),
+            (Style::Plain, " "),
+            (Style::Operator, "|"),
+            (Style::Plain, " "),
+            (Style::Command, "cat"),
+        ]);
+        assert_eq!(styles("'ls'"), vec![(Style::Quoted, "'ls'")]);
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(styles("if ls; then x; fi"), vec![
+            (Style::Keyword, "if"),
+            (Style::Plain, " "),
+            (Style::Command, "ls"),
+            (Style::Operator, ";"),
+            (Style::Plain, " "),
+            (Style::Keyword, "then"),
+            (Style::Plain, " "),
+            (Style::UnknownCommand, "x"),
+            (Style::Operator, ";"),
+            (Style::Plain, " "),
+            (Style::Keyword, "fi"),
+        ]);
+        assert_eq!(styles("echo if"), vec![(Style::Command, "echo"), (Style::Plain, " if")]);
+    }
+
+    #[test]
+    fn quotes_and_variables() {
+        assert_eq!(styles("echo \"a b\" $x"), vec![
+            (Style::Command, "echo"),
+            (Style::Plain, " "),
+            (Style::Quoted, "\"a b\""),
+            (Style::Plain, " "),
+            (Style::Variable, "$x"),
+        ]);
+        assert_eq!(styles("echo $(ls a)b ${x:-a}/b"), vec![
+            (Style::Command, "echo"),
+            (Style::Plain, " "),
+            (Style::Variable, "$(ls a)b"),
+            (Style::Plain, " "),
+            (Style::Variable, "${x:-a}/b"),
+        ]);
+        assert_eq!(styles("x=1"), vec![
+            (Style::Variable, "x"),
+            (Style::Operator, "="),
+            (Style::Plain, "1"),
+        ]);
+        assert_eq!(styles("cat <<EOF\n$x\nEOF\nls"), vec![
+            (Style::Command, "cat"),
+            (Style::Plain, " "),
+            (Style::Operator, "<<"),
+            (Style::Plain, "EOF\n"),
+            (Style::Quoted, "$x\nEOF\n"),
+            (Style::Command, "ls"),
+        ]);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert_eq!(styles("echo 'a"), vec![
+            (Style::Command, "echo"),
+            (Style::Plain, " "),
+            (Style::Incomplete, "'a"),
+        ]);
+        assert_eq!(styles("ls; echo $(ls a"), vec![
+            (Style::Command, "ls"),
+            (Style::Operator, ";"),
+            (Style::Plain, " "),
+            (Style::Command, "echo"),
+            (Style::Plain, " "),
+            (Style::Incomplete, "$(ls a"),
+        ]);
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(styles("ls frames /frames frames/frame0 frames/x"), vec![
+            (Style::Command, "ls"),
+            (Style::Plain, " "),
+            (Style::Directory, "frames"),
+            (Style::Plain, " "),
+            (Style::Directory, "/frames"),
+            (Style::Plain, " "),
+            (Style::Resource, "frames/frame0"),
+            (Style::Plain, " frames/x"),
+        ]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(styles("ls # a | b\nls"), vec![
+            (Style::Command, "ls"),
+            (Style::Plain, " "),
+            (Style::Comment, "# a | b"),
+            (Style::Plain, "\n"),
+            (Style::Command, "ls"),
+        ]);
+    }
+}
@@ -0,0 +1,81 @@
+use lighthouse_client::{Lighthouse, TokioWebSocket};
+use std::borrow::Cow;
+
+use colored::Colorize;
+use rustyline::{completion::{Completer, Pair}, highlight::{CmdKind, Highlighter}, hint::{Hinter, HistoryHinter}, validate::{ValidationContext, ValidationResult, Validator}, Context as LineContext};
+
+use crate::{context::Context, line::{self, IncompleteInput}};
+
+use self::complete::ShellCompleter;
+
+mod complete;
+mod highlight;
+
+/// The rustyline helper of the interactive shell.
+pub struct ShellHelper {
+    completer: ShellCompleter,
+    hinter: HistoryHinter,
+}
+
+impl ShellHelper {
+    pub fn new(lh: Lighthouse<TokioWebSocket>) -> Self {
+        Self { completer: ShellCompleter::new(lh), hinter: HistoryHinter::new() }
+    }
+
+    /// Updates the helper with the state of the shell, e.g. the working
+    /// directory. Should be called before reading each line.
+    pub fn refresh(&mut self, ctx: &Context) {
+        self.completer.refresh(ctx);
+    }
+
+    /// Updates the helper after interpreting the line, e.g. by dropping
+    /// cached listings that the line may have invalidated.
+    pub fn invalidate_after(&mut self, line: &str) {
+        self.completer.invalidate_after(line);
+    }
+}
+
+impl rustyline::Helper for ShellHelper {}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &LineContext<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        self.completer.complete(line, pos, ctx)
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &LineContext<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight::highlight(line, |word| self.completer.is_command(word), |path| self.completer.cached_is_directory(path)))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dimmed().to_string())
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        // Any typed character may change the highlighting of the whole line,
+        // e.g. by opening a quote
+        true
+    }
+}
+
+impl Validator for ShellHelper {
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        // Keep reading lines while the input is incomplete, e.g. in an unclosed
+        // quote or block. Other errors are reported when interpreting the line.
+        match line::parse(ctx.input()) {
+            Err(e) if e.is::<IncompleteInput>() => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
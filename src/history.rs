@@ -0,0 +1,11 @@
+use std::{env, path::PathBuf};
+
+/// The path of the file persisting the interactive history for the given
+/// server host, located in the XDG data directory (usually ~/.local/share).
+pub fn history_path(host: &str) -> Option<PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home::home_dir().map(|h| h.join(".local").join("share")))?;
+    Some(data_dir.join(env!("CARGO_PKG_NAME")).join(format!("history-{}", host)))
+}
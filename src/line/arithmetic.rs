@@ -0,0 +1,373 @@
+use anyhow::{bail, Result};
+
+use super::error::IncompleteInput;
+
+/// An integer arithmetic expression, e.g. in `$((x + 1))`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Variable(String),
+    Unary { op: UnaryOperator, operand: Box<Expression> },
+    Binary { op: BinaryOperator, lhs: Box<Expression>, rhs: Box<Expression> },
+    /// Assigns the value to the variable, optionally combined with its
+    /// current value (e.g. `x += 1`).
+    Assign { variable: String, op: Option<BinaryOperator>, value: Box<Expression> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Plus,
+    Minus,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// The binary operators with their precedence, from loosest to tightest
+/// binding, like in C.
+const BINARY_OPERATORS: [(&str, BinaryOperator, u8); 18] = [
+    ("||", BinaryOperator::Or, 1),
+    ("&&", BinaryOperator::And, 2),
+    ("|", BinaryOperator::BitOr, 3),
+    ("^", BinaryOperator::BitXor, 4),
+    ("&", BinaryOperator::BitAnd, 5),
+    ("==", BinaryOperator::Eq, 6),
+    ("!=", BinaryOperator::Ne, 6),
+    ("<", BinaryOperator::Lt, 7),
+    ("<=", BinaryOperator::Le, 7),
+    (">", BinaryOperator::Gt, 7),
+    (">=", BinaryOperator::Ge, 7),
+    ("<<", BinaryOperator::Shl, 8),
+    (">>", BinaryOperator::Shr, 8),
+    ("+", BinaryOperator::Add, 9),
+    ("-", BinaryOperator::Sub, 9),
+    ("*", BinaryOperator::Mul, 10),
+    ("/", BinaryOperator::Div, 10),
+    ("%", BinaryOperator::Rem, 10),
+];
+
+const UNARY_OPERATORS: [(&str, UnaryOperator); 4] = [
+    ("+", UnaryOperator::Plus),
+    ("-", UnaryOperator::Minus),
+    ("!", UnaryOperator::Not),
+    ("~", UnaryOperator::BitNot),
+];
+
+/// The assignment operators, mapping to the operator combining the current value.
+const ASSIGNMENT_OPERATORS: [(&str, Option<BinaryOperator>); 6] = [
+    ("=", None),
+    ("+=", Some(BinaryOperator::Add)),
+    ("-=", Some(BinaryOperator::Sub)),
+    ("*=", Some(BinaryOperator::Mul)),
+    ("/=", Some(BinaryOperator::Div)),
+    ("%=", Some(BinaryOperator::Rem)),
+];
+
+/// The operator symbols, longest first so they are tokenized greedily.
+const OPERATOR_SYMBOLS: [&str; 25] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+    LeftParen,
+    RightParen,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let Ok(n) = rest[..len].parse() else {
+                bail!("Invalid number in arithmetic expression: {}", &rest[..len]);
+            };
+            tokens.push(Token::Number(n));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            // Like in POSIX shells, variables may be referenced with or without $
+            let start = if c == '$' { 1 } else { 0 };
+            let len = rest[start..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).map_or(rest.len(), |i| start + i);
+            if len == start {
+                bail!("Expected variable name after $ in arithmetic expression");
+            }
+            tokens.push(Token::Name(rest[start..len].to_owned()));
+            len
+        } else if c == '(' {
+            tokens.push(Token::LeftParen);
+            1
+        } else if c == ')' {
+            tokens.push(Token::RightParen);
+            1
+        } else if let Some(op) = OPERATOR_SYMBOLS.into_iter().find(|op| rest.starts_with(op)) {
+            tokens.push(Token::Operator(op));
+            op.len()
+        } else if rest.starts_with('=') {
+            tokens.push(Token::Operator("="));
+            1
+        } else {
+            bail!("Unexpected {} in arithmetic expression", c);
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses an arithmetic expression.
+pub fn parse(text: &str) -> Result<Expression> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    let expression = parser.parse_assignment()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {:?} in arithmetic expression", token);
+    }
+    Ok(expression)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expression> {
+        if let [Token::Name(variable), Token::Operator(op), ..] = &self.tokens[self.pos..] {
+            if let Some((_, combined)) = ASSIGNMENT_OPERATORS.into_iter().find(|(o, _)| o == op) {
+                self.pos += 2;
+                let value = self.parse_assignment()?;
+                return Ok(Expression::Assign { variable: variable.clone(), op: combined, value: Box::new(value) });
+            }
+        }
+        self.parse_binary(0)
+    }
+
+    /// Parses binary operations binding tighter than the given precedence
+    /// using precedence climbing. All binary operators are left-associative.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expression> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(Token::Operator(symbol)) = self.peek() {
+            let Some((_, op, precedence)) = BINARY_OPERATORS.into_iter().find(|(s, _, _)| s == symbol) else {
+                break;
+            };
+            if precedence <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(precedence)?;
+            lhs = Expression::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression> {
+        match self.next().cloned() {
+            Some(Token::Number(n)) => Ok(Expression::Number(n)),
+            Some(Token::Name(name)) => Ok(Expression::Variable(name)),
+            Some(Token::LeftParen) => {
+                let inner = self.parse_assignment()?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(inner),
+                    Some(token) => bail!("Expected ) in arithmetic expression, but got {:?}", token),
+                    None => bail!(IncompleteInput("Expected ) in arithmetic expression, but reached the end".to_owned())),
+                }
+            },
+            Some(Token::Operator(symbol)) => match UNARY_OPERATORS.into_iter().find(|(s, _)| *s == symbol) {
+                Some((_, op)) => Ok(Expression::Unary { op, operand: Box::new(self.parse_unary()?) }),
+                None => bail!("Unexpected {} in arithmetic expression", symbol),
+            },
+            Some(Token::RightParen) => bail!("Unexpected ) in arithmetic expression"),
+            None => bail!("Expected operand in arithmetic expression, but reached the end"),
+        }
+    }
+}
+
+/// Evaluates the expression, looking up variables with the given function.
+/// Like in POSIX shells, unset and empty variables evaluate to 0. Returns the
+/// value along with the assignments made by the expression, in order.
+pub fn evaluate(expression: &Expression, lookup: impl Fn(&str) -> Option<String>) -> Result<(i64, Vec<(String, i64)>)> {
+    let mut evaluator = Evaluator { lookup, assignments: Vec::new() };
+    let value = evaluator.evaluate(expression)?;
+    Ok((value, evaluator.assignments))
+}
+
+struct Evaluator<F> {
+    lookup: F,
+    assignments: Vec<(String, i64)>,
+}
+
+impl<F> Evaluator<F> where F: Fn(&str) -> Option<String> {
+    fn variable(&self, name: &str) -> Result<i64> {
+        if let Some((_, value)) = self.assignments.iter().rev().find(|(n, _)| n == name) {
+            return Ok(*value);
+        }
+        let value = (self.lookup)(name).unwrap_or_default();
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        let Ok(value) = value.parse() else {
+            bail!("{}: Not an integer: {}", name, value);
+        };
+        Ok(value)
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> Result<i64> {
+        Ok(match expression {
+            Expression::Number(n) => *n,
+            Expression::Variable(name) => self.variable(name)?,
+            Expression::Unary { op, operand } => {
+                let operand = self.evaluate(operand)?;
+                match op {
+                    UnaryOperator::Plus => operand,
+                    UnaryOperator::Minus => operand.wrapping_neg(),
+                    UnaryOperator::Not => (operand == 0) as i64,
+                    UnaryOperator::BitNot => !operand,
+                }
+            },
+            // Logical operators short-circuit
+            Expression::Binary { op: BinaryOperator::And, lhs, rhs } => {
+                (self.evaluate(lhs)? != 0 && self.evaluate(rhs)? != 0) as i64
+            },
+            Expression::Binary { op: BinaryOperator::Or, lhs, rhs } => {
+                (self.evaluate(lhs)? != 0 || self.evaluate(rhs)? != 0) as i64
+            },
+            Expression::Binary { op, lhs, rhs } => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                apply(*op, lhs, rhs)?
+            },
+            Expression::Assign { variable, op, value } => {
+                let mut value = self.evaluate(value)?;
+                if let Some(op) = op {
+                    value = apply(*op, self.variable(variable)?, value)?;
+                }
+                self.assignments.push((variable.clone(), value));
+                value
+            },
+        })
+    }
+}
+
+fn apply(op: BinaryOperator, lhs: i64, rhs: i64) -> Result<i64> {
+    if matches!(op, BinaryOperator::Div | BinaryOperator::Rem) && rhs == 0 {
+        bail!("Division by zero in arithmetic expression");
+    }
+    Ok(match op {
+        BinaryOperator::Add => lhs.wrapping_add(rhs),
+        BinaryOperator::Sub => lhs.wrapping_sub(rhs),
+        BinaryOperator::Mul => lhs.wrapping_mul(rhs),
+        BinaryOperator::Div => lhs.wrapping_div(rhs),
+        BinaryOperator::Rem => lhs.wrapping_rem(rhs),
+        BinaryOperator::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOperator::Shr => lhs.wrapping_shr(rhs as u32),
+        BinaryOperator::Lt => (lhs < rhs) as i64,
+        BinaryOperator::Le => (lhs <= rhs) as i64,
+        BinaryOperator::Gt => (lhs > rhs) as i64,
+        BinaryOperator::Ge => (lhs >= rhs) as i64,
+        BinaryOperator::Eq => (lhs == rhs) as i64,
+        BinaryOperator::Ne => (lhs != rhs) as i64,
+        BinaryOperator::BitAnd => lhs & rhs,
+        BinaryOperator::BitXor => lhs ^ rhs,
+        BinaryOperator::BitOr => lhs | rhs,
+        BinaryOperator::And => (lhs != 0 && rhs != 0) as i64,
+        BinaryOperator::Or => (lhs != 0 || rhs != 0) as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{evaluate, parse};
+
+    fn eval_with(text: &str, variables: &[(&str, &str)]) -> (i64, Vec<(String, i64)>) {
+        let variables: HashMap<&str, &str> = variables.iter().copied().collect();
+        evaluate(&parse(text).unwrap(), |name| variables.get(name).map(|v| v.to_string())).unwrap()
+    }
+
+    fn eval(text: &str) -> i64 {
+        eval_with(text, &[]).0
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(eval("1 + 2"), 3);
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("10 - 4 - 3"), 3);
+        assert_eq!(eval("7 / 2"), 3);
+        assert_eq!(eval("-7 % 3"), -1);
+        assert_eq!(eval("-(2 + 3)"), -5);
+        assert_eq!(eval("1 << 4 | 1"), 17);
+        assert_eq!(eval("6 & 3 ^ 1"), 3);
+        assert_eq!(eval("~0"), -1);
+        assert_eq!(eval("!0 + !5"), 1);
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(eval("1 < 2"), 1);
+        assert_eq!(eval("2 <= 1"), 0);
+        assert_eq!(eval("3 == 3 && 4 != 4"), 0);
+        assert_eq!(eval("0 || 2 > 1"), 1);
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(eval_with("x * 2 + $y", &[("x", "3"), ("y", "1")]).0, 7);
+        assert_eq!(eval_with("unset + 1", &[]).0, 1);
+        assert_eq!(eval_with("$1 + 1", &[("1", "41")]).0, 42);
+        assert_eq!(eval_with("x = 5", &[]), (5, vec![("x".to_owned(), 5)]));
+        assert_eq!(eval_with("x += 2", &[("x", "1")]), (3, vec![("x".to_owned(), 3)]));
+        assert_eq!(eval_with("(x = 2) + x", &[]).0, 4);
+        assert_eq!(eval_with("0 && (x = 1)", &[]), (0, vec![]));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("").is_err());
+        assert!(parse("1 +").is_err());
+        assert!(parse("(1").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("1 = 2").is_err());
+        assert!(parse("12abc").is_err());
+        assert!(evaluate(&parse("1 / 0").unwrap(), |_| None).is_err());
+        assert!(evaluate(&parse("x").unwrap(), |_| Some("abc".to_owned())).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{error::Error, fmt, ops::Range};
+
+/// A byte range in the source of a line or script.
+pub type Span = Range<usize>;
+
+/// A syntax error caused by input that ended prematurely, e.g. in an unclosed
+/// quote or block. Unlike other syntax errors, these can be fixed by reading
+/// more input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteInput(pub String);
+
+impl fmt::Display for IncompleteInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for IncompleteInput {}
+
+/// An error located in the source, e.g. a syntax error or a failing statement
+/// in a script. Attached as context, so the underlying error (e.g. an
+/// `IncompleteInput`) can still be downcast to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError {
+    /// The message of the underlying error.
+    pub message: String,
+    /// The file the source was read from, if any.
+    pub origin: Option<String>,
+    /// The 1-based line number.
+    pub line: usize,
+    /// The 1-based column (in characters).
+    pub column: usize,
+    /// The text of the offending line.
+    pub source_line: String,
+    /// The number of characters to underline, at least one.
+    pub width: usize,
+    /// Whether the source spans multiple lines, in which case the position is
+    /// shown even without an origin.
+    pub is_multiline: bool,
+}
+
+impl SourceError {
+    /// Locates the error at the span of the source. If the error is already
+    /// located, e.g. in a command substitution, its message is kept while the
+    /// span is replaced by the given (outer) one.
+    pub fn locate(error: anyhow::Error, source: &str, span: Span) -> anyhow::Error {
+        // Only errors from the same file are relocated, e.g. from sourced scripts
+        // we keep the full location
+        let message = match error.downcast_ref::<SourceError>() {
+            Some(located) if located.origin.is_none() && error.to_string() == located.to_string() => located.message.clone(),
+            _ => error.to_string(),
+        };
+        let start = floor_char_boundary(source, span.start);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let end = floor_char_boundary(source, span.end).clamp(start, line_end);
+        let located = SourceError {
+            message,
+            origin: None,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            source_line: source[line_start..line_end].to_owned(),
+            width: source[start..end].chars().count().max(1),
+            is_multiline: source.trim_end().contains('\n'),
+        };
+        error.context(located)
+    }
+
+    /// Attributes the error to the given file, if it is located (and not
+    /// attributed already, e.g. in a sourced script).
+    pub fn set_origin(error: &mut anyhow::Error, origin: &str) {
+        if let Some(located) = error.downcast_mut::<SourceError>() {
+            located.origin.get_or_insert_with(|| origin.to_owned());
+        }
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = &self.origin {
+            write!(f, "{}:{}:{}: ", origin, self.line, self.column)?;
+        } else if self.is_multiline {
+            write!(f, "{}:{}: ", self.line, self.column)?;
+        }
+        writeln!(f, "{}", self.message.trim())?;
+        writeln!(f, "  {}", self.source_line)?;
+        write!(f, "  {}{}", " ".repeat(self.column - 1), "^".repeat(self.width))
+    }
+}
+
+impl Error for SourceError {}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    i = i.min(s.len());
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::{IncompleteInput, SourceError};
+
+    #[test]
+    fn locations() {
+        let error = SourceError::locate(anyhow!("Parse error: Unexpected )"), "echo a )", 7..8);
+        assert_eq!(error.to_string(), "Parse error: Unexpected )\n  echo a )\n         ^");
+
+        let mut error = SourceError::locate(anyhow!("Oops"), "ls\necho 'abc\nls", 8..12);
+        assert_eq!(error.to_string(), "2:6: Oops\n  echo 'abc\n       ^^^^");
+        SourceError::set_origin(&mut error, "a.limo");
+        assert_eq!(error.to_string(), "a.limo:2:6: Oops\n  echo 'abc\n       ^^^^");
+    }
+
+    #[test]
+    fn nested_locations() {
+        let inner = SourceError::locate(IncompleteInput("Unclosed".to_owned()).into(), "ls '", 3..4);
+        let outer = SourceError::locate(inner, "echo $(ls ')", 5..12);
+        assert_eq!(outer.to_string(), "Unclosed\n  echo $(ls ')\n       ^^^^^^^");
+        assert!(outer.is::<IncompleteInput>());
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+use lighthouse_client::protocol::DirectoryTree;
+
+use crate::{context::Context, path::{VirtualPathBuf, SEPARATOR}};
+
+use super::pattern;
+
+/// The path component matching any number of nested directories.
+const RECURSIVE_COMPONENT: &str = "**";
+
+/// Expands the glob pattern against the resource tree on the server, returning
+/// the matching paths in sorted order (like `ls`). Leading components without
+/// special characters are kept as written, e.g. `../a*` expands to `../ab`.
+pub async fn expand(glob: &str, ctx: &Context) -> Result<Vec<String>> {
+    let components: Vec<&str> = glob.split(SEPARATOR).collect();
+    let literal_count = components.iter().take_while(|c| !pattern::is_pattern(c)).count();
+    let (literal, patterns) = components.split_at(literal_count);
+
+    let literal: Vec<String> = literal.iter().map(|c| pattern::unescape(c)).collect();
+    let prefix = if literal.is_empty() { String::new() } else { literal.join(SEPARATOR) + SEPARATOR };
+    let base = ctx.cwd.join(VirtualPathBuf::from(literal.join(SEPARATOR).as_str()));
+
+    let mut matches = Vec::new();
+    if let Ok(response) = ctx.lh.list(&base.as_lh_vec()).await {
+        collect_matches(&response.payload, patterns, &mut Vec::new(), &mut matches);
+    }
+    if matches.is_empty() {
+        bail!("No matches found: {}", pattern::unescape(glob));
+    }
+
+    let mut paths: Vec<String> = matches.into_iter()
+        .map(|path| prefix.clone() + &path.join(SEPARATOR))
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Collects the paths (relative to the tree) matching the pattern components.
+fn collect_matches(tree: &DirectoryTree, patterns: &[&str], path: &mut Vec<String>, matches: &mut Vec<Vec<String>>) {
+    let Some((&current, rest)) = patterns.split_first() else {
+        matches.push(path.clone());
+        return;
+    };
+
+    if current.is_empty() && rest.is_empty() {
+        // A trailing separator only matches directories, which the tree is
+        path.push(String::new());
+        matches.push(path.clone());
+        path.pop();
+        return;
+    }
+
+    if current == RECURSIVE_COMPONENT && !rest.is_empty() {
+        // Match zero directories here, more are matched by recursing below
+        collect_matches(tree, rest, path, matches);
+    }
+
+    for (name, child) in &tree.entries {
+        let is_recursive = current == RECURSIVE_COMPONENT;
+        if !is_recursive && !pattern::matches(current, name) {
+            continue;
+        }
+        path.push(name.clone());
+        if rest.is_empty() {
+            matches.push(path.clone());
+        }
+        if let Some(child) = child {
+            let child_patterns = if is_recursive { patterns } else { rest };
+            if !child_patterns.is_empty() {
+                collect_matches(child, child_patterns, path, matches);
+            }
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lighthouse_client::protocol::DirectoryTree;
+
+    use super::collect_matches;
+
+    fn dir<const N: usize>(entries: [(&str, Option<DirectoryTree>); N]) -> Option<DirectoryTree> {
+        Some(DirectoryTree {
+            entries: entries.into_iter().map(|(name, child)| (name.to_owned(), child)).collect::<HashMap<_, _>>(),
+        })
+    }
+
+    fn tree() -> DirectoryTree {
+        dir([
+            ("tmp1", None),
+            ("tmp2", None),
+            ("scene.json", None),
+            ("user", dir([
+                ("a", dir([("tmp3", None)])),
+                ("b", dir([])),
+            ])),
+        ]).unwrap()
+    }
+
+    fn glob(glob: &str) -> Vec<String> {
+        let patterns: Vec<&str> = glob.split('/').collect();
+        let mut matches = Vec::new();
+        collect_matches(&tree(), &patterns, &mut Vec::new(), &mut matches);
+        let mut paths: Vec<String> = matches.into_iter().map(|p| p.join("/")).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(glob("tmp*"), vec!["tmp1", "tmp2"]);
+        assert_eq!(glob("*.json"), vec!["scene.json"]);
+        assert_eq!(glob("tmp[2-9]"), vec!["tmp2"]);
+        assert_eq!(glob("user/?"), vec!["user/a", "user/b"]);
+        assert_eq!(glob("*/*/tmp*"), vec!["user/a/tmp3"]);
+        assert_eq!(glob("*/"), vec!["user/"]);
+        assert_eq!(glob("x*"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn recursive_wildcards() {
+        assert_eq!(glob("**/tmp*"), vec!["tmp1", "tmp2", "user/a/tmp3"]);
+        assert_eq!(glob("user/**"), vec!["user/a", "user/a/tmp3", "user/b"]);
+        assert_eq!(glob("**/b"), vec!["user/b"]);
+    }
+}
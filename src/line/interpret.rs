@@ -1,74 +1,560 @@
-use anyhow::{bail, Result};
+use std::env;
+
+use anyhow::{bail, Context as _, Error, Result};
 use async_recursion::async_recursion;
-use lighthouse_client::protocol::{to_value, Value};
+use lighthouse_client::protocol::{from_value, to_value, Value};
+use tokio::{fs::{self, OpenOptions}, io::AsyncWriteExt};
+
+use crate::{cmd, context::Context, path::VirtualPathBuf, status::{ExitStatus, HandledFailure}, value};
+
+use super::{report_error, with_origin, arithmetic::{self, Expression}, error::{SourceError, Span}, parse::{parse, Argument, Assignment, Command, Fragment, InputSource, Parameter, ParameterOperation, RedirectFormat, RedirectMode, Statement, StatementList}, glob, pattern};
+
+/// The special variable holding the exit status of the last statement.
+const STATUS_VARIABLE: &str = "?";
+/// The special variable holding all positional arguments (excluding $0).
+const ALL_ARGS_VARIABLE: &str = "@";
+/// The special variable holding the number of positional arguments (excluding $0).
+const ARG_COUNT_VARIABLE: &str = "#";
+/// The maximum nesting depth of function calls (and sourced scripts), which
+/// stops runaway recursion before it overflows the stack.
+const MAX_CALL_DEPTH: usize = 100;
+/// The prefix of redirect targets on the local disk, e.g. `> file:out.json`.
+const LOCAL_PATH_PREFIX: &str = "file:";
+
+pub async fn interpret(list: StatementList, ctx: &mut Context) -> Result<()> {
+    interpret_list(list, &mut Output::Stdout, ctx).await
+}
+
+/// The destination for the output of interpreted statements.
+enum Output {
+    /// Prints the output to stdout.
+    Stdout,
+    /// Collects the output, e.g. for a command substitution.
+    Captured(Vec<Value>),
+}
+
+impl Output {
+    fn write(&mut self, output: Value) {
+        // Values are only converted to text when printed
+        let text = value::to_text(&output);
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        match self {
+            Self::Stdout => println!("{}", text),
+            Self::Captured(captured) => captured.push(output),
+        }
+    }
+
+    /// The captured output as text, one line per output.
+    fn into_captured(self) -> String {
+        match self {
+            Self::Stdout => String::new(),
+            Self::Captured(captured) => captured.iter()
+                .map(|output| value::to_text(output).trim().to_owned() + "\n")
+                .collect(),
+        }
+    }
 
-use crate::{cmd, context::Context, path::VirtualPathBuf};
+    /// The captured output as a value. A single output (e.g. of `cat`) is kept
+    /// as-is, multiple outputs are joined to text.
+    fn into_value(mut self) -> Value {
+        match &mut self {
+            Self::Captured(captured) if captured.len() == 1 => captured.pop().unwrap(),
+            _ => Value::from(self.into_captured()),
+        }
+    }
+}
 
-use super::parse::{Argument, Assignment, Command, Fragment, Statement};
+/// Interprets the top-level statements of a script in order. Like in
+/// statement lists, failing statements are reported without stopping the
+/// script, which then fails with the status of the last failing statement
+/// (so callers like CI jobs can detect it). Failures handled by `&&` or `||`
+/// do not count. In scripts read from a file, errors are located at the
+/// failing statement.
+pub async fn interpret_script(statements: Vec<(Statement, Span)>, source: &str, origin: Option<&str>, ctx: &mut Context) -> Result<()> {
+    let mut failure = None;
+    for (statement, span) in statements {
+        let Err(e) = interpret_statement(statement, &mut Output::Stdout, ctx).await else {
+            continue;
+        };
+        if e.is::<HandledFailure>() {
+            continue;
+        }
+        let e = match origin {
+            Some(origin) => with_origin(SourceError::locate(e, source, span), origin),
+            None => e,
+        };
+        report_error(&e);
+        failure = Some(ExitStatus::of(&e));
+    }
+    match failure {
+        Some(status) => {
+            ctx.status = status;
+            Err(Error::new(status))
+        },
+        None => Ok(()),
+    }
+}
+
+/// Calls a user-defined function with the given arguments (including the
+/// function name) and returns its output. Like in POSIX shells, the arguments
+/// after the name replace $1 and onwards while $0 stays the caller's.
+pub async fn call_function(body: StatementList, args: &[String], ctx: &mut Context) -> Result<Value> {
+    if ctx.call_depth >= MAX_CALL_DEPTH {
+        bail!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH);
+    }
+    let positional_args = function_args(&ctx.positional_args, args);
+    let caller_args = std::mem::replace(&mut ctx.positional_args, positional_args);
+    let mut out = Output::Captured(Vec::new());
+    ctx.call_depth += 1;
+    let result = interpret_list(body, &mut out, ctx).await;
+    ctx.call_depth -= 1;
+    ctx.positional_args = caller_args;
+    // The call as a whole fails, even if the failure was handled within it
+    if let Err(e) = result {
+        return Err(match e.downcast_ref::<HandledFailure>() {
+            Some(HandledFailure(status)) => Error::new(*status),
+            None => e,
+        });
+    }
+    Ok(out.into_value())
+}
 
-pub async fn interpret(stmt: Statement, ctx: &mut Context) -> Result<()> {
-    match stmt {
-        Statement::Assignment(assignment) => interpret_assignment(assignment, ctx).await?,
-        Statement::Command(command) => {
-            let interpretation = interpret_command(command, ctx).await?;
+/// The positional arguments of a function called with the given arguments,
+/// i.e. the caller's $0 followed by the arguments after the function name.
+fn function_args(caller_args: &[String], args: &[String]) -> Vec<String> {
+    caller_args.iter().take(1).chain(args.iter().skip(1)).cloned().collect()
+}
+
+/// Interprets the statements in order. Like in POSIX shells, a failing
+/// statement does not prevent subsequent statements from running, the list
+/// only fails if its last statement fails.
+#[async_recursion]
+async fn interpret_list(list: StatementList, out: &mut Output, ctx: &mut Context) -> Result<()> {
+    let mut result = Ok(());
+    for statement in list.statements {
+        if let Err(e) = result {
+            report_error(&e);
+        }
+        result = interpret_statement(statement, out, ctx).await;
+    }
+    result
+}
+
+#[async_recursion]
+async fn interpret_statement(stmt: Statement, out: &mut Output, ctx: &mut Context) -> Result<()> {
+    let result = match stmt {
+        Statement::Assignment(assignment) => interpret_assignment(assignment, ctx).await,
+        Statement::Command(command) => interpret_command(command, None, ctx).await.map(|interpretation| {
             if !interpretation.redirected {
                 // Print output if not redirected
-                let output = interpretation.output.trim();
-                if !output.is_empty() {
-                    println!("{}", output);
+                out.write(interpretation.output);
+            }
+        }),
+        Statement::And { lhs, rhs } => match interpret_statement(*lhs, out, ctx).await {
+            Ok(()) => interpret_statement(*rhs, out, ctx).await,
+            // A nested chain (e.g. in a && b && c) has reported its failure already
+            Err(e) if e.is::<HandledFailure>() => Err(e),
+            Err(e) => {
+                report_error(&e);
+                Err(Error::new(HandledFailure(ExitStatus::of(&e))))
+            },
+        },
+        Statement::Or { lhs, rhs } => match interpret_statement(*lhs, out, ctx).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                report_error(&e);
+                interpret_statement(*rhs, out, ctx).await
+            },
+        },
+        Statement::If { condition, then, otherwise } => match interpret_list(condition, out, ctx).await {
+            Ok(()) => interpret_list(then, out, ctx).await,
+            // A failing condition only selects the branch, so it isn't reported
+            Err(_) => match otherwise {
+                Some(otherwise) => interpret_list(otherwise, out, ctx).await,
+                None => Ok(()),
+            },
+        },
+        Statement::For { variable, values, body } => {
+            let values = evaluate_words(values, ctx).await?;
+            let mut result = Ok(());
+            for value in values {
+                if let Err(e) = result {
+                    report_error(&e);
                 }
+                ctx.variables.insert(variable.clone(), value.into());
+                result = interpret_list(body.clone(), out, ctx).await;
             }
+            result
         },
-    }
-    Ok(())
+        Statement::While { condition, body } => {
+            let mut result = Ok(());
+            loop {
+                if interpret_list(condition.clone(), out, ctx).await.is_err() {
+                    break;
+                }
+                if let Err(e) = result {
+                    report_error(&e);
+                }
+                result = interpret_list(body.clone(), out, ctx).await;
+            }
+            result
+        },
+        Statement::Function { name, body } => {
+            ctx.functions.insert(name, body);
+            Ok(())
+        },
+    };
+    // Track the status of the statement for $?
+    ctx.status = ExitStatus::of_result(&result);
+    result
 }
 
 struct Interpretation {
-    output: String,
+    output: Value,
     redirected: bool,
 }
 
 async fn interpret_assignment(assignment: Assignment, ctx: &mut Context) -> Result<()> {
     let lhs = evaluate_argument(assignment.lhs, ctx).await?;
-    let rhs = evaluate_argument(assignment.rhs, ctx).await?;
+    let rhs = evaluate_value(assignment.rhs, ctx).await?;
     ctx.variables.insert(lhs, rhs);
     Ok(())
 }
 
 #[async_recursion]
-async fn interpret_command(command: Command, ctx: &mut Context) -> Result<Interpretation> {
+async fn interpret_command(command: Command, input: Option<Value>, ctx: &mut Context) -> Result<Interpretation> {
     match command {
         Command::Invocation { args } => {
             if args.is_empty() {
                 bail!("Cannot interpret empty invocation");
             }
+            // Aliases are expanded before evaluating the arguments, so they are
+            // evaluated (and globbed) for the command the alias stands for
+            if let Some((name, expanded)) = expand_alias(&args, ctx)? {
+                ctx.expanding_aliases.push(name);
+                let result = interpret_alias(expanded, input, ctx).await;
+                ctx.expanding_aliases.pop();
+                return result;
+            }
             let args = evaluate_arguments(args, ctx).await?;
-            let output = cmd::invoke(&args, ctx).await?;
+            let output = cmd::invoke(&args, input, ctx).await?;
             Ok(Interpretation {
                 output,
                 redirected: false,
             })
         },
-        Command::Redirect { inner, path } => {
-            // The redirected output is interpreted as JSON and then written as MessagePack
-            let inner = interpret_command(*inner, ctx).await?;
+        Command::Input { inner, source } => {
+            // The input replaces whatever would have been piped into the command
+            let input = match source {
+                InputSource::File(path) => {
+                    let path = evaluate_argument(path, ctx).await?;
+                    fs::read_to_string(&path).await
+                        .with_context(|| format!("Could not read {}", path))?
+                },
+                InputSource::Here(body) => evaluate_argument(body, ctx).await?,
+            }.into();
+            interpret_command(*inner, Some(input), ctx).await
+        },
+        Command::Redirect { inner, path, mode, format } => {
+            let inner = interpret_command(*inner, input, ctx).await?;
             let path = evaluate_argument(path, ctx).await?;
-            let path = ctx.cwd.join(VirtualPathBuf::from(path.as_str()));
-            let json_value: serde_json::Value = serde_json::from_str(&inner.output)?;
-            let lh_value: Value = to_value(json_value)?;
-            ctx.lh.post(&path.as_lh_vec(), lh_value).await?;
+            redirect(inner.output.clone(), &path, mode, format, ctx).await
+                .with_context(|| format!("Could not redirect to {}", path))?;
             Ok(Interpretation {
                 output: inner.output,
                 redirected: true,
             })
         },
+        Command::Pipe { lhs, rhs } => {
+            // Like in POSIX shells, a redirected command pipes no output
+            let lhs = interpret_command(*lhs, input, ctx).await?;
+            let piped = if lhs.redirected { Value::from("") } else { lhs.output };
+            interpret_command(*rhs, Some(piped), ctx).await
+        },
     }
 }
 
+/// Writes the output to the redirect target, which is either a resource or,
+/// if prefixed accordingly, a local file.
+async fn redirect(output: Value, path: &str, mode: RedirectMode, format: RedirectFormat, ctx: &Context) -> Result<()> {
+    let value = decode_output(output, format)?;
+    if let Some(local_path) = path.strip_prefix(LOCAL_PATH_PREFIX) {
+        write_local(local_path, value, mode).await
+    } else {
+        let path = ctx.cwd.join(VirtualPathBuf::from(path));
+        match mode {
+            RedirectMode::Replace => { ctx.lh.post(&path.as_lh_vec(), value).await?; },
+            RedirectMode::Merge => merge_into(&path, value, ctx).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Interprets redirected output as a value in the given format. Output that is
+/// not text (e.g. from `cat`) is already a value and thus kept as-is, unless a
+/// string or bytes are requested.
+fn decode_output(output: Value, format: RedirectFormat) -> Result<Value> {
+    let text = match (output, format) {
+        (Value::String(s), _) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        (Value::Binary(bytes), RedirectFormat::Msgpack) => return decode_msgpack(&bytes),
+        (output @ Value::Binary(_), RedirectFormat::Bytes) => return Ok(output),
+        (output, RedirectFormat::String | RedirectFormat::Bytes) => value::to_text(&output),
+        (output, _) => return Ok(output),
+    };
+    Ok(match format {
+        RedirectFormat::Auto => match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(json_value) => to_value(json_value)?,
+            Err(_) => Value::from(text),
+        },
+        RedirectFormat::Json => {
+            let json_value: serde_json::Value = serde_json::from_str(&text)
+                .context("Output is not valid JSON (use --as string to write it as a string)")?;
+            to_value(json_value)?
+        },
+        RedirectFormat::Msgpack => decode_msgpack(text.as_bytes())?,
+        RedirectFormat::String => Value::from(text),
+        RedirectFormat::Bytes => Value::Binary(text.into_bytes()),
+    })
+}
+
+fn decode_msgpack(mut bytes: &[u8]) -> Result<Value> {
+    rmpv::decode::read_value(&mut bytes).context("Output is not valid MessagePack")
+}
+
+/// Writes the value to a local file. Strings and binary values are written
+/// as-is, anything else as pretty-printed JSON. Like in POSIX shells, merging
+/// appends to the file.
+async fn write_local(path: &str, value: Value, mode: RedirectMode) -> Result<()> {
+    let contents = match value {
+        Value::String(s) => s.into_bytes(),
+        Value::Binary(bytes) => bytes,
+        value => {
+            let json_value: serde_json::Value = from_value(value)?;
+            (serde_json::to_string_pretty(&json_value)? + "\n").into_bytes()
+        },
+    };
+    match mode {
+        RedirectMode::Replace => fs::write(path, contents).await?,
+        RedirectMode::Merge => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+            file.write_all(&contents).await?;
+        },
+    }
+    Ok(())
+}
+
+/// Merges the value into the resource at the given path, creating it if it does
+/// not exist yet (like `>>` does with files).
+async fn merge_into(path: &VirtualPathBuf, value: Value, ctx: &Context) -> Result<()> {
+    let path = path.as_lh_vec();
+    match ctx.lh.get::<Value>(&path).await {
+        Ok(response) => {
+            let mut merged = response.payload;
+            value::merge(&mut merged, value);
+            ctx.lh.put(&path, merged).await?;
+        },
+        Err(lighthouse_client::Error::Server { code: 404, .. }) => {
+            ctx.lh.post(&path, value).await?;
+        },
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Expands the alias named by the first (literal) argument, if any, by parsing
+/// its value and passing the remaining, still unevaluated arguments to its last
+/// command. Returns the alias name along with the expansion. Like in POSIX
+/// shells, an alias is not expanded again within its own expansion (e.g.
+/// `alias ls='ls -l'`).
+fn expand_alias(args: &[Argument], ctx: &Context) -> Result<Option<(String, StatementList)>> {
+    let Some(Fragment::Literal(name)) = lone_fragment(&args[0]) else {
+        return Ok(None);
+    };
+    let Some(value) = ctx.aliases.get(name).filter(|_| !ctx.expanding_aliases.contains(name)) else {
+        return Ok(None);
+    };
+    let mut expanded = parse(value).with_context(|| format!("Could not expand alias {}", name))?;
+    append_alias_arguments(&mut expanded, &args[1..])
+        .with_context(|| format!("Could not expand alias {}", name))?;
+    Ok(Some((name.clone(), expanded)))
+}
+
+/// Passes the arguments to the last command of an alias' expansion.
+fn append_alias_arguments(expanded: &mut StatementList, args: &[Argument]) -> Result<()> {
+    if args.is_empty() {
+        return Ok(());
+    }
+    let Some(last_args) = expanded.statements.last_mut().and_then(last_invocation_args) else {
+        bail!("The alias does not end in a command that takes arguments");
+    };
+    last_args.extend_from_slice(args);
+    Ok(())
+}
+
+/// The arguments of the invocation that runs last in the statement, e.g. `b`
+/// in `a | b > out`, to which the arguments of an alias are passed.
+fn last_invocation_args(statement: &mut Statement) -> Option<&mut Vec<Argument>> {
+    fn command_args(command: &mut Command) -> Option<&mut Vec<Argument>> {
+        match command {
+            Command::Invocation { args } => Some(args),
+            Command::Input { inner, .. } | Command::Redirect { inner, .. } => command_args(inner),
+            Command::Pipe { rhs, .. } => command_args(rhs),
+        }
+    }
+    match statement {
+        Statement::Command(command) => command_args(command),
+        Statement::And { rhs, .. } | Statement::Or { rhs, .. } => last_invocation_args(rhs),
+        _ => None,
+    }
+}
+
+/// Interprets an expanded alias. A single command is interpreted as such, so
+/// that it receives the input and can be redirected (e.g. in a pipeline).
+async fn interpret_alias(expanded: StatementList, input: Option<Value>, ctx: &mut Context) -> Result<Interpretation> {
+    let statements = match <[Statement; 1]>::try_from(expanded.statements) {
+        Ok([Statement::Command(command)]) => return interpret_command(command, input, ctx).await,
+        Ok(statements) => Vec::from(statements),
+        Err(statements) => statements,
+    };
+    let mut out = Output::Captured(Vec::new());
+    interpret_list(StatementList { statements }, &mut out, ctx).await?;
+    Ok(Interpretation {
+        output: out.into_value(),
+        redirected: false,
+    })
+}
+
+/// Evaluates the arguments of an invocation, expanding glob patterns unless
+/// the command takes them as written (see `globs_argument`).
 async fn evaluate_arguments(args: Vec<Argument>, ctx: &mut Context) -> Result<Vec<String>> {
+    evaluate_args(args, true, ctx).await
+}
+
+/// Evaluates a list of words, e.g. the values of a for loop, expanding glob
+/// patterns.
+async fn evaluate_words(words: Vec<Argument>, ctx: &mut Context) -> Result<Vec<String>> {
+    evaluate_args(words, false, ctx).await
+}
+
+async fn evaluate_args(args: Vec<Argument>, is_invocation: bool, ctx: &mut Context) -> Result<Vec<String>> {
+    let has_split = |arg: &Argument| arg.fragments.iter().any(|f| matches!(f, Fragment::Split(_)));
     let mut evaluated = Vec::new();
     for arg in args {
-        evaluated.push(evaluate_argument(arg, ctx).await?);
+        if is_all_args(&arg) {
+            // Like "$@" in POSIX shells, expand to one argument per positional argument
+            evaluated.extend(ctx.positional_args.iter().skip(1).cloned());
+        } else if (!is_invocation || globs_argument(&evaluated)) && arg.fragments.iter().any(|f| matches!(f, Fragment::Glob(_))) {
+            let pattern = evaluate_pattern(arg, ctx).await?;
+            if pattern::is_pattern(&pattern) {
+                evaluated.extend(glob::expand(&pattern, ctx).await?);
+            } else {
+                evaluated.push(pattern::unescape(&pattern));
+            }
+        } else if has_split(&arg) {
+            let mut parts = Vec::new();
+            for fragment in arg.fragments {
+                let is_split = matches!(fragment, Fragment::Split(_));
+                parts.push((evaluate_fragment(fragment, ctx).await?, is_split));
+            }
+            evaluated.extend(split_words(parts));
+        } else {
+            evaluated.push(evaluate_argument(arg, ctx).await?);
+        }
+    }
+    Ok(evaluated)
+}
+
+/// Whether the next argument of an invocation is expanded as a glob pattern,
+/// given the arguments preceding it. Arithmetic expressions (e.g. `let x=2*3`)
+/// as well as query filters and patched fields (e.g. `query .frame[0] model`
+/// or `patch model .frame[0] 3`) use glob characters themselves, so they are
+/// passed on as written.
+fn globs_argument(preceding: &[String]) -> bool {
+    match preceding.split_first() {
+        Some((command, _)) if command == "let" || command == "expr" => false,
+        // Only the filter, i.e. the first argument after the options
+        Some((command, args)) if command == "query" || command == "jq" => args.iter().any(|arg| !arg.starts_with('-')),
+        // Only the field, i.e. the first argument after the resource path
+        Some((command, args)) if command == "patch" => args.iter().filter(|arg| !arg.starts_with('-')).count() != 1,
+        _ => true,
+    }
+}
+
+/// Whether the argument consists of nothing but $@.
+fn is_all_args(arg: &Argument) -> bool {
+    let is_all_args = |fragment: &Fragment| matches!(fragment, Fragment::Variable(v) if v == ALL_ARGS_VARIABLE);
+    match lone_fragment(arg) {
+        Some(Fragment::Split(inner)) => is_all_args(inner),
+        Some(fragment) => is_all_args(fragment),
+        None => false,
+    }
+}
+
+/// Joins the evaluated parts of an argument into words, splitting the parts
+/// from unquoted substitutions at whitespace (e.g. `a$(echo b c)` to `ab` and
+/// `c`). Like in POSIX shells, an empty substitution produces no word.
+fn split_words(parts: Vec<(String, bool)>) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    for (text, is_split) in parts {
+        if !is_split {
+            if !text.is_empty() {
+                current.get_or_insert_with(String::new).push_str(&text);
+            }
+            continue;
+        }
+        if text.starts_with(char::is_whitespace) {
+            words.extend(current.take());
+        }
+        for (i, field) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                words.extend(current.take());
+            }
+            current.get_or_insert_with(String::new).push_str(field);
+        }
+        if text.ends_with(char::is_whitespace) {
+            words.extend(current.take());
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// The single non-empty fragment of the argument, if any.
+fn lone_fragment(arg: &Argument) -> Option<&Fragment> {
+    let mut non_empty = arg.fragments.iter().filter(|f| !matches!(f, Fragment::Literal(lit) if lit.is_empty()));
+    non_empty.next().filter(|_| non_empty.next().is_none())
+}
+
+/// Evaluates the argument to a value. Unlike `evaluate_argument`, a lone
+/// variable or command substitution keeps its value, e.g. a binary frame from
+/// `$(cat model)`, instead of converting it to text.
+async fn evaluate_value(arg: Argument, ctx: &mut Context) -> Result<Value> {
+    match lone_fragment(&arg) {
+        Some(Fragment::Variable(variable)) | Some(Fragment::Parameter(Parameter { name: variable, operation: ParameterOperation::Value })) => {
+            lookup_value(variable, ctx).with_context(|| format!("Unbound variable: {}", variable))
+        },
+        Some(Fragment::Command(list)) => {
+            let mut out = Output::Captured(Vec::new());
+            interpret_list(list.clone(), &mut out, ctx).await?;
+            Ok(out.into_value())
+        },
+        _ => Ok(evaluate_argument(arg, ctx).await?.into()),
+    }
+}
+
+/// Evaluates the argument to a glob pattern. Only unquoted glob characters
+/// have a special meaning, everything else is matched literally.
+async fn evaluate_pattern(arg: Argument, ctx: &mut Context) -> Result<String> {
+    let mut evaluated = String::new();
+    for fragment in arg.fragments {
+        match fragment {
+            Fragment::Glob(glob) => evaluated.push_str(&glob),
+            fragment => evaluated.push_str(&pattern::escape(&evaluate_fragment(fragment, ctx).await?)),
+        }
     }
     Ok(evaluated)
 }
@@ -81,15 +567,281 @@ async fn evaluate_argument(arg: Argument, ctx: &mut Context) -> Result<String> {
     Ok(evaluated)
 }
 
+/// Looks up the value of a variable. Special variables and positional arguments
+/// take precedence over shell variables, which take precedence over variables
+/// from the process environment (e.g. $HOME).
+fn lookup_variable(variable: &str, ctx: &Context) -> Option<String> {
+    match variable {
+        STATUS_VARIABLE => Some(ctx.status.code().to_string()),
+        ALL_ARGS_VARIABLE => Some(ctx.positional_args.iter().skip(1).cloned().collect::<Vec<_>>().join(" ")),
+        ARG_COUNT_VARIABLE => Some(ctx.positional_args.len().saturating_sub(1).to_string()),
+        _ if !variable.is_empty() && variable.chars().all(|c| c.is_ascii_digit()) => {
+            // Like in POSIX shells, unset positional arguments expand to the empty string
+            let index: usize = variable.parse().ok()?;
+            Some(ctx.positional_args.get(index).cloned().unwrap_or_default())
+        },
+        _ => ctx.variables.get(variable).map(value::to_text).or_else(|| env::var(variable).ok()),
+    }
+}
+
+/// Looks up the value of a variable like `lookup_variable`, but without
+/// converting shell variables to text.
+fn lookup_value(variable: &str, ctx: &Context) -> Option<Value> {
+    match ctx.variables.get(variable) {
+        Some(value) if is_assignable(variable) => Some(value.clone()),
+        _ => lookup_variable(variable, ctx).map(Value::from),
+    }
+}
+
+#[async_recursion]
+async fn evaluate_parameter(parameter: Parameter, ctx: &mut Context) -> Result<String> {
+    let Parameter { name, operation } = parameter;
+    let value = lookup_variable(&name, ctx);
+    // Like in POSIX shells, the forms with a colon treat empty values as unset
+    let non_empty = value.clone().filter(|v| !v.is_empty());
+    let require_value = || value.clone().with_context(|| format!("Unbound variable: {}", name));
+    Ok(match operation {
+        ParameterOperation::Value => require_value()?,
+        ParameterOperation::Length => parameter_length(value.as_deref()),
+        ParameterOperation::Default(word) => match non_empty {
+            Some(value) => value,
+            None => evaluate_argument(word, ctx).await?,
+        },
+        ParameterOperation::AssignDefault(word) => match non_empty {
+            Some(value) => value,
+            None => {
+                if !is_assignable(&name) {
+                    bail!("{}: cannot assign in this way", name);
+                }
+                let value = evaluate_argument(word, ctx).await?;
+                ctx.variables.insert(name, value.clone().into());
+                value
+            },
+        },
+        ParameterOperation::Require(word) => match non_empty {
+            Some(value) => value,
+            None => {
+                let message = evaluate_argument(word, ctx).await?;
+                let message = if message.is_empty() { "parameter null or not set".to_owned() } else { message };
+                bail!("{}: {}", name, message);
+            },
+        },
+        ParameterOperation::RemovePrefix { pattern, longest } => {
+            let value = require_value()?;
+            let pattern = evaluate_pattern(pattern, ctx).await?;
+            let mut ends: Vec<usize> = value.char_indices().map(|(i, _)| i).chain([value.len()]).collect();
+            if longest {
+                ends.reverse();
+            }
+            match ends.into_iter().find(|&i| pattern::matches(&pattern, &value[..i])) {
+                Some(i) => value[i..].to_owned(),
+                None => value,
+            }
+        },
+        ParameterOperation::RemoveSuffix { pattern, longest } => {
+            let value = require_value()?;
+            let pattern = evaluate_pattern(pattern, ctx).await?;
+            let mut starts: Vec<usize> = value.char_indices().map(|(i, _)| i).chain([value.len()]).collect();
+            if !longest {
+                starts.reverse();
+            }
+            match starts.into_iter().find(|&i| pattern::matches(&pattern, &value[i..])) {
+                Some(i) => value[..i].to_owned(),
+                None => value,
+            }
+        },
+    })
+}
+
+/// The length of a parameter's value in characters, where (like in POSIX
+/// shells) unset parameters count as empty, e.g. `${#unset}` is 0.
+fn parameter_length(value: Option<&str>) -> String {
+    value.unwrap_or_default().chars().count().to_string()
+}
+
+/// Evaluates the arithmetic expression, performing its assignments.
+pub fn evaluate_arithmetic(expression: &Expression, ctx: &mut Context) -> Result<i64> {
+    let (value, assignments) = arithmetic::evaluate(expression, |name| lookup_variable(name, ctx))?;
+    for (variable, value) in assignments {
+        if !is_assignable(&variable) {
+            bail!("{}: cannot assign in this way", variable);
+        }
+        ctx.variables.insert(variable, value.into());
+    }
+    Ok(value)
+}
+
+/// Whether the variable can be assigned, i.e. is not special or positional.
+fn is_assignable(variable: &str) -> bool {
+    variable.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+#[async_recursion]
 async fn evaluate_fragment(fragment: Fragment, ctx: &mut Context) -> Result<String> {
     match fragment {
-        Fragment::Literal(lit) => Ok(lit),
+        Fragment::Split(inner) => evaluate_fragment(*inner, ctx).await,
+        Fragment::Literal(lit) | Fragment::Glob(lit) => Ok(lit),
         Fragment::Variable(variable) => {
-            let Some(value) = ctx.variables.get(&variable) else {
+            let Some(value) = lookup_variable(&variable, ctx) else {
                 bail!("Unbound variable: {}", variable)
             };
-            Ok(value.to_owned())
+            Ok(value)
+        },
+        Fragment::Tilde(username) => {
+            let username = if username.is_empty() { &ctx.username } else { &username };
+            Ok(VirtualPathBuf::home(username).to_string())
         },
-        Fragment::Command(command) => Ok(interpret_command(command, ctx).await?.output),
+        Fragment::Arithmetic(expression) => Ok(evaluate_arithmetic(&expression, ctx)?.to_string()),
+        Fragment::Parameter(parameter) => evaluate_parameter(parameter, ctx).await,
+        Fragment::Command(list) => {
+            // Like in POSIX shells, trailing newlines are removed from the output
+            let mut out = Output::Captured(Vec::new());
+            interpret_list(list, &mut out, ctx).await?;
+            Ok(out.into_captured().trim_end_matches('\n').to_owned())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lighthouse_client::protocol::{to_value, Value};
+    use serde_json::json;
+
+    use crate::{filter, line::{arithmetic, parse::{parse, Argument, Command, Fragment, RedirectFormat, Statement}}};
+
+    use super::{append_alias_arguments, decode_output, function_args, globs_argument, parameter_length, split_words};
+
+    /// The arguments of the single invocation in the line.
+    fn invocation_args(line: &str) -> Vec<Argument> {
+        let Ok([Statement::Command(Command::Invocation { args })]) = <[Statement; 1]>::try_from(parse(line).unwrap().statements) else {
+            panic!("Expected a single invocation in {}", line);
+        };
+        args
+    }
+
+    /// Evaluates the argument as written, i.e. without glob expansion.
+    fn literal(arg: &Argument) -> String {
+        arg.fragments.iter()
+            .map(|fragment| match fragment {
+                Fragment::Literal(text) | Fragment::Glob(text) => text.as_str(),
+                fragment => panic!("Unexpected {:?}", fragment),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn redirect_formats() {
+        let decode = |output: &str, format| decode_output(Value::from(output), format);
+        assert_eq!(decode("{\"x\": [1]}", RedirectFormat::Auto).unwrap(), to_value(json!({"x": [1]})).unwrap());
+        assert_eq!(decode("hello", RedirectFormat::Auto).unwrap(), Value::from("hello"));
+        assert_eq!(decode("1", RedirectFormat::String).unwrap(), Value::from("1"));
+        assert_eq!(decode("ab", RedirectFormat::Bytes).unwrap(), Value::Binary(vec![b'a', b'b']));
+        assert_eq!(decode("\u{7}", RedirectFormat::Msgpack).unwrap(), Value::from(7));
+        assert!(decode("hello", RedirectFormat::Json).is_err());
+    }
+
+    #[test]
+    fn typed_redirects() {
+        let frame = Value::Binary(vec![0, 255, 3]);
+        assert_eq!(decode_output(frame.clone(), RedirectFormat::Auto).unwrap(), frame);
+        assert_eq!(decode_output(frame.clone(), RedirectFormat::Json).unwrap(), frame);
+        assert_eq!(decode_output(frame.clone(), RedirectFormat::Bytes).unwrap(), frame);
+        assert_eq!(decode_output(Value::Binary(vec![7]), RedirectFormat::Msgpack).unwrap(), Value::from(7));
+        assert_eq!(decode_output(Value::from(1), RedirectFormat::String).unwrap(), Value::from("1"));
+    }
+
+    #[test]
+    fn unglobbed_arguments() {
+        assert!(globs_argument(&["ls".to_owned()]));
+        assert!(globs_argument(&[]));
+
+        // let x=2*3 reaches the arithmetic evaluation as written
+        let args = invocation_args("let x=2*3");
+        assert!(args[1].fragments.iter().any(|f| matches!(f, Fragment::Glob(_))));
+        assert!(!globs_argument(&["let".to_owned()]));
+        let expression = arithmetic::parse(&literal(&args[1])).unwrap();
+        assert_eq!(arithmetic::evaluate(&expression, |_| None).unwrap(), (6, vec![("x".to_owned(), 6)]));
+
+        let args = invocation_args("expr 2 * 3");
+        assert!(!globs_argument(&["expr".to_owned(), "2".to_owned()]));
+        assert_eq!(literal(&args[2]), "*");
+    }
+
+    #[test]
+    fn unglobbed_filters() {
+        let strings = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+
+        // query .frame[0] model reaches the filter parser as written
+        let args = invocation_args("query .frame[0] model");
+        assert!(args[1].fragments.iter().any(|f| matches!(f, Fragment::Glob(_))));
+        assert!(!globs_argument(&strings(&["query"])));
+        let filter = filter::parse(&literal(&args[1])).unwrap();
+        assert_eq!(filter.as_path(), Some(vec![Value::from("frame"), Value::from(0)]));
+
+        assert!(!globs_argument(&strings(&["jq", "-r", "-c"])));
+        assert!(globs_argument(&strings(&["query", "-r", ".[]"])));
+        assert!(globs_argument(&strings(&["cat", ".frame[0]"])));
+    }
+
+    #[test]
+    fn unglobbed_fields() {
+        let strings = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+
+        // patch x .a[0] 1 reaches the filter parser as written
+        let args = invocation_args("patch x .a[0] 1");
+        assert!(args[2].fragments.iter().any(|f| matches!(f, Fragment::Glob(_))));
+        assert!(!globs_argument(&strings(&["patch", "x"])));
+        let filter = filter::parse(&literal(&args[2])).unwrap();
+        assert_eq!(filter.as_path(), Some(vec![Value::from("a"), Value::from(0)]));
+
+        assert!(!globs_argument(&strings(&["patch", "-u", "x"])));
+        assert!(globs_argument(&strings(&["patch"])));
+        assert!(globs_argument(&strings(&["patch", "-u"])));
+        assert!(globs_argument(&strings(&["patch", "x", ".a[0]"])));
+    }
+
+    #[test]
+    fn word_splitting() {
+        let split = |parts: &[(&str, bool)]| split_words(parts.iter().map(|&(text, is_split)| (text.to_owned(), is_split)).collect());
+        assert_eq!(split(&[("a\nb c\n", true)]), ["a", "b", "c"]);
+        assert_eq!(split(&[("x", false), ("a b", true), ("y", false)]), ["xa", "by"]);
+        assert_eq!(split(&[("x", false), (" a ", true), ("y", false)]), ["x", "a", "y"]);
+        assert_eq!(split(&[("a b", false)]), ["a b"]);
+        assert!(split(&[("", false), ("  ", true)]).is_empty());
+    }
+
+    #[test]
+    fn function_arguments() {
+        let strings = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+        assert_eq!(function_args(&strings(&["script.limo", "x"]), &strings(&["f", "a", "b"])), ["script.limo", "a", "b"]);
+        assert_eq!(function_args(&strings(&["limo"]), &strings(&["f"])), ["limo"]);
+    }
+
+    #[test]
+    fn parameter_lengths() {
+        assert_eq!(parameter_length(Some("äbc")), "3");
+        assert_eq!(parameter_length(Some("")), "0");
+        assert_eq!(parameter_length(None), "0");
+    }
+
+    #[test]
+    fn alias_arguments() {
+        let args = invocation_args("q .frame[0] model");
+
+        // The arguments reach the aliased command unevaluated, so its glob exemptions apply
+        let mut expanded = parse("query -r").unwrap();
+        append_alias_arguments(&mut expanded, &args[1..]).unwrap();
+        let Statement::Command(Command::Invocation { args: expanded_args }) = &expanded.statements[0] else {
+            panic!("Expected an invocation: {:?}", expanded);
+        };
+        assert_eq!(expanded_args.iter().map(literal).collect::<Vec<_>>(), ["query", "-r", ".frame[0]", "model"]);
+        assert_eq!(expanded_args[2], args[1]);
+
+        let mut expanded = parse("cd a; ls | wc > out").unwrap();
+        append_alias_arguments(&mut expanded, &args[1..]).unwrap();
+        assert_eq!(expanded, parse("cd a; ls | wc .frame[0] model > out").unwrap());
+
+        assert!(append_alias_arguments(&mut parse("x=1").unwrap(), &args[1..]).is_err());
+        assert!(append_alias_arguments(&mut parse("x=1").unwrap(), &[]).is_ok());
     }
 }
@@ -1,28 +1,32 @@
+use std::fmt;
+
 use anyhow::{bail, Error, Result};
 
+use super::error::{IncompleteInput, SourceError, Span};
+
 macro_rules! operators {
-    ($(($op_name_upper:ident, $op_name_lower:ident, $op_char:literal)),* $(,)?) => {
+    ($(($op_name_upper:ident, $op_name_lower:ident, $op_str:literal)),* $(,)?) => {
         /// An operator understood by the lexer.
         #[derive(Debug, Clone, PartialEq, Eq)]
         pub enum Operator {
             $($op_name_upper,)*
         }
 
-        impl From<Operator> for char {
+        impl From<Operator> for &'static str {
             fn from(op: Operator) -> Self {
                 match op {
-                    $(Operator::$op_name_upper => $op_char,)*
+                    $(Operator::$op_name_upper => $op_str,)*
                 }
             }
         }
 
-        impl TryFrom<char> for Operator {
+        impl TryFrom<&str> for Operator {
             type Error = Error;
 
-            fn try_from(c: char) -> Result<Self> {
-                match c {
-                    $($op_char => Ok(Operator::$op_name_upper),)*
-                    _ => bail!("Unknown operator: {}", c),
+            fn try_from(s: &str) -> Result<Self> {
+                match s {
+                    $($op_str => Ok(Operator::$op_name_upper),)*
+                    _ => bail!("Unknown operator: {}", s),
                 }
             }
         }
@@ -35,8 +39,27 @@ macro_rules! operators {
 }
 
 operators! {
-    (Redirect, redirect, '>'),
-    (Assign, assign, '='),
+    (Redirect, redirect, ">"),
+    (AppendRedirect, append_redirect, ">>"),
+    (InputRedirect, input_redirect, "<"),
+    (Heredoc, heredoc, "<<"),
+    (Assign, assign, "="),
+    (Pipe, pipe, "|"),
+    (And, and, "&&"),
+    (Or, or, "||"),
+    (Semicolon, semicolon, ";"),
+    (Newline, newline, "\n"),
+    (LeftParen, left_paren, "("),
+    (RightParen, right_paren, ")"),
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Newline => write!(f, "newline"),
+            _ => write!(f, "{}", <&str>::from(self.clone())),
+        }
+    }
 }
 
 /// The kind of string segment.
@@ -46,24 +69,70 @@ pub enum SegmentKind {
     Literal,
     /// A variable interpolation segment.
     Variable,
+    /// A braced parameter expansion segment, holding the unparsed text between the braces.
+    Parameter,
+    /// An unquoted glob segment, holding special characters like `*`.
+    Glob,
+    /// An unquoted brace expansion character, i.e. `{`, `,` or `}`.
+    Brace,
+    /// An unquoted tilde prefix, holding the (possibly empty) username after the `~`.
+    Tilde,
+    /// A command substitution segment, holding the unparsed source of the command.
+    Command,
+    /// An arithmetic expansion segment, holding the unparsed expression.
+    Arithmetic,
 }
 
 /// A fragment of a string token.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Segment {
-    /// The text of the segment. Excludes the interpolation character ($) if not literal.
+    /// The text of the segment. Excludes the interpolation character ($) and,
+    /// for command substitutions and parameter expansions, the enclosing
+    /// parentheses or braces if not literal.
     pub text: String,
     /// The kind of string segment. Indicates e.g. whether this segment represents an interpolation.
     pub kind: SegmentKind,
+    /// Whether this is an interpolation enclosed in double quotes (or a
+    /// here-document), which protects its value from word splitting.
+    pub is_quoted: bool,
 }
 
 impl Segment {
     pub fn empty_literal() -> Self {
-        Self { text: String::new(), kind: SegmentKind::Literal }
+        Self { text: String::new(), kind: SegmentKind::Literal, is_quoted: false }
     }
 
     pub fn empty_variable() -> Self {
-        Self { text: String::new(), kind: SegmentKind::Variable }
+        Self { text: String::new(), kind: SegmentKind::Variable, is_quoted: false }
+    }
+
+    pub fn command(source: String) -> Self {
+        Self { text: source, kind: SegmentKind::Command, is_quoted: false }
+    }
+
+    pub fn arithmetic(expression: String) -> Self {
+        Self { text: expression, kind: SegmentKind::Arithmetic, is_quoted: false }
+    }
+
+    pub fn parameter(text: String) -> Self {
+        Self { text, kind: SegmentKind::Parameter, is_quoted: false }
+    }
+
+    pub fn empty_glob() -> Self {
+        Self { text: String::new(), kind: SegmentKind::Glob, is_quoted: false }
+    }
+
+    pub fn brace(c: char) -> Self {
+        Self { text: c.to_string(), kind: SegmentKind::Brace, is_quoted: false }
+    }
+
+    pub fn tilde(username: String) -> Self {
+        Self { text: username, kind: SegmentKind::Tilde, is_quoted: false }
+    }
+
+    /// Whether this is a variable segment whose name is still being read.
+    fn is_open(&self) -> bool {
+        self.kind == SegmentKind::Variable && self.text.is_empty()
     }
 }
 
@@ -74,41 +143,253 @@ pub enum Token {
     String(Vec<Segment>),
 }
 
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Operator(op) => write!(f, "{}", op),
+            Self::String(segments) => {
+                for segment in segments {
+                    match segment.kind {
+                        SegmentKind::Literal | SegmentKind::Glob | SegmentKind::Brace => write!(f, "{}", segment.text)?,
+                        SegmentKind::Tilde => write!(f, "~{}", segment.text)?,
+                        SegmentKind::Variable => write!(f, "${}", segment.text)?,
+                        SegmentKind::Parameter => write!(f, "${{{}}}", segment.text)?,
+                        SegmentKind::Command => write!(f, "$({})", segment.text)?,
+                        SegmentKind::Arithmetic => write!(f, "$(({}))", segment.text)?,
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
 const INTERPOLATION_CHAR: char = '$';
 const ESCAPE_CHAR: char = '\\';
+const COMMENT_CHAR: char = '#';
+const HISTORY_CHAR: char = '!';
+const SUBSTITUTION_OPEN_CHAR: char = '(';
+const SUBSTITUTION_CLOSE_CHAR: char = ')';
+const PARAMETER_OPEN_CHAR: char = '{';
+const PARAMETER_CLOSE_CHAR: char = '}';
+
+/// Characters with a special meaning in glob patterns if unquoted.
+const GLOB_CHARS: [char; 4] = ['*', '?', '[', ']'];
+
+/// Characters with a special meaning in brace expansions if unquoted.
+const BRACE_CHARS: [char; 3] = ['{', ',', '}'];
+
+/// The character that expands to a home directory at the start of a word.
+const TILDE_CHAR: char = '~';
+
+/// Single-character names of special variables, e.g. `$?`.
+const SPECIAL_VARIABLE_CHARS: [char; 3] = ['?', '@', '#'];
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Lexes the start of an interpolation, assuming that the interpolation
+/// character ($) has already been consumed. Command substitutions, arithmetic
+/// and parameter expansions and special variables are lexed completely, whereas regular
+/// variables are returned as an open segment whose name is read by the caller.
+fn lex_interpolation(it: &mut Chars) -> Result<Segment> {
+    if it.next_if_eq(&SUBSTITUTION_OPEN_CHAR).is_some() {
+        if it.next_if_eq(&SUBSTITUTION_OPEN_CHAR).is_some() {
+            // Arithmetic expansion, i.e. $((...))
+            let expression = lex_substitution(it)?;
+            if it.next_if_eq(&SUBSTITUTION_CLOSE_CHAR).is_none() {
+                bail!(IncompleteInput("Unexpectedly reached end of arithmetic expansion, did you close it with ))?".to_owned()));
+            }
+            return Ok(Segment::arithmetic(expression));
+        }
+        Ok(Segment::command(lex_substitution(it)?))
+    } else if it.next_if_eq(&PARAMETER_OPEN_CHAR).is_some() {
+        Ok(Segment::parameter(lex_parameter(it)?))
+    } else if let Some(c) = it.next_if(|c| SPECIAL_VARIABLE_CHARS.contains(c)) {
+        Ok(Segment { text: c.to_string(), kind: SegmentKind::Variable, is_quoted: false })
+    } else {
+        Ok(Segment::empty_variable())
+    }
+}
+
+/// Creates a string token from the segments. Since a single brace character
+/// cannot form a brace expansion, it is made literal, e.g. for the `{` keyword.
+fn string_token(segments: Vec<Segment>) -> Token {
+    if segments.iter().filter(|s| s.kind == SegmentKind::Brace).count() == 1
+        && segments.iter().all(|s| matches!(s.kind, SegmentKind::Literal | SegmentKind::Brace)) {
+        let text = segments.into_iter().map(|s| s.text).collect();
+        return Token::String(vec![Segment { text, kind: SegmentKind::Literal, is_quoted: false }]);
+    }
+    Token::String(segments)
+}
+
+/// Quotes the string such that it is lexed as a single literal string again.
+pub fn quote(s: &str) -> String {
+    let escaped = s.replace(ESCAPE_CHAR, "\\\\").replace('\'', "\\'");
+    format!("'{}'", escaped)
+}
+
+/// A cursor over the characters of the source. Unlike `Peekable<Chars>`, this
+/// keeps track of the byte offset, which is needed for spans.
+#[derive(Debug, Clone)]
+struct Chars<'a> {
+    source: &'a str,
+    offset: usize,
+    peeked: Option<char>,
+}
+
+impl<'a> Chars<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { source, offset: 0, peeked: None }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.peeked = self.source[self.offset..].chars().next();
+        self.peeked.as_ref()
+    }
+
+    fn next_if(&mut self, predicate: impl FnOnce(&char) -> bool) -> Option<char> {
+        let c = *self.peek()?;
+        predicate(&c).then(|| {
+            self.offset += c.len_utf8();
+            c
+        })
+    }
 
-/// Tokenizes the line. This handles quoting and removes whitespace.
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|c| c == expected)
+    }
+}
+
+impl Iterator for Chars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.source[self.offset..].chars().next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+}
+
+/// Tokenizes the line (or script). This handles quoting and removes whitespace
+/// and comments. Newlines are preserved as operators since they separate statements.
 pub fn lex(line: &str) -> Result<Vec<Token>> {
-    let mut tokens = Vec::<Token>::new();
+    Ok(lex_spanned(line)?.into_iter().map(|(token, _)| token).collect())
+}
+
+/// Tokenizes the line like `lex`, but also returns the span of each token.
+/// Errors are located at the start of the construct that failed to lex,
+/// e.g. the opening quote of an unclosed string.
+pub fn lex_spanned(line: &str) -> Result<Vec<(Token, Span)>> {
+    let mut error_span = 0..0;
+    lex_tokens(line, &mut error_span).map_err(|e| SourceError::locate(e, line, error_span))
+}
+
+/// Tokenizes as much of the line as possible, e.g. to highlight it while it
+/// is being typed. Returns the tokens before the first construct that failed
+/// to lex (e.g. an unclosed quote) along with the offset of that construct.
+pub fn lex_partial(line: &str) -> (Vec<(Token, Span)>, Option<usize>) {
+    let mut error_span = 0..0;
+    match lex_tokens(line, &mut error_span) {
+        Ok(tokens) => (tokens, None),
+        Err(_) => {
+            // The error is located at the start of the failing construct, so
+            // the line before it is strictly shorter
+            let (tokens, offset) = lex_partial(&line[..error_span.start]);
+            (tokens, Some(offset.unwrap_or(error_span.start)))
+        },
+    }
+}
+
+fn lex_tokens(line: &str, error_span: &mut Span) -> Result<Vec<(Token, Span)>> {
+    let mut tokens = Vec::<(Token, Span)>::new();
     let mut current: Option<Vec<Segment>> = None;
-    let mut it = line.chars().into_iter();
-    while let Some(c) = it.next() {
-        if let Ok(op) = Operator::try_from(c) { // Operator
+    let mut current_start = 0;
+    let mut pending_heredocs = Vec::<(usize, HeredocDelimiter)>::new();
+    let mut it = Chars::new(line);
+    loop {
+        let start = it.offset;
+        let Some(c) = it.next() else {
+            break;
+        };
+        *error_span = start..it.offset;
+        if current.is_none() {
+            current_start = start;
+        }
+        if c == ESCAPE_CHAR { // Unquoted escape
+            match it.next() {
+                // Like in POSIX shells, an escaped newline continues the line
+                Some('\n') => {},
+                Some(c) => {
+                    let segments = current.get_or_insert_with(|| vec![Segment::empty_literal()]);
+                    if segments.last().unwrap().kind != SegmentKind::Literal {
+                        segments.push(Segment::empty_literal());
+                    }
+                    segments.last_mut().unwrap().text.push(c);
+                },
+                None => bail!(IncompleteInput(format!("Unexpectedly reached end after {}, expected escaped character or line continuation", ESCAPE_CHAR))),
+            }
+        } else if let Some(op) = lex_operator(c, &mut it) { // Operator
             if let Some(current) = current.take() {
-                tokens.push(Token::String(current));
+                tokens.push((string_token(current), current_start..start));
             }
             current = None;
-            tokens.push(Token::Operator(op));
+            tokens.push((Token::Operator(op.clone()), start..it.offset));
+            match op {
+                Operator::Heredoc => {
+                    // The body is read after the end of the line, we insert a placeholder for now
+                    let delimiter_start = it.offset;
+                    let delimiter = lex_heredoc_delimiter(&mut it)?;
+                    pending_heredocs.push((tokens.len(), delimiter));
+                    tokens.push((Token::String(Vec::new()), delimiter_start..it.offset));
+                },
+                Operator::Newline => {
+                    for (index, delimiter) in pending_heredocs.drain(..) {
+                        *error_span = tokens[index - 1].1.clone();
+                        tokens[index].0 = Token::String(lex_heredoc_body(&mut it, &delimiter)?);
+                    }
+                },
+                _ => {},
+            }
+        } else if c == COMMENT_CHAR && current.is_none() { // Comment
+            while it.next_if(|&c| c != '\n').is_some() {}
         } else if c == '\'' || c == '"' { // Opening quote
             let quote = c;
             let mut is_escaped = false;
             let mut in_interpolation = false;
-            if current.is_none() {
-                current = Some(vec![Segment::empty_literal()]);
+            let segments = current.get_or_insert_with(|| vec![Segment::empty_literal()]);
+            if segments.last().unwrap().kind != SegmentKind::Literal {
+                // Exiting unquoted interpolation
+                segments.push(Segment::empty_literal());
             }
             loop {
                 let Some(c) = it.next() else {
-                    bail!("Unexpectedly reached end of {}-quoted string", quote);
+                    bail!(IncompleteInput(format!("Unexpectedly reached end of {}-quoted string", quote)));
                 };
                 if !is_escaped && c == ESCAPE_CHAR {
+                    if in_interpolation {
+                        // Exiting interpolation
+                        in_interpolation = false;
+                        current.as_mut().unwrap().push(Segment::empty_literal());
+                    }
                     is_escaped = true;
+                } else if is_escaped && quote == '"' && c == '\n' {
+                    // Line continuation
+                    is_escaped = false;
                 } else if !is_escaped && quote == '"' && c == INTERPOLATION_CHAR {
                     // Entering interpolation
-                    in_interpolation = true;
-                    current.as_mut().unwrap().push(Segment::empty_variable());
+                    let mut segment = lex_interpolation(&mut it)?;
+                    segment.is_quoted = true;
+                    in_interpolation = segment.is_open();
+                    let segments = current.as_mut().unwrap();
+                    segments.push(segment);
+                    if !in_interpolation {
+                        segments.push(Segment::empty_literal());
+                    }
                 } else {
                     if !is_escaped {
-                        if in_interpolation && !c.is_ascii_alphanumeric() && c != '_' {
+                        if in_interpolation && !is_identifier_char(c) {
                             // Exiting interpolation
                             in_interpolation = false;
                             current.as_mut().unwrap().push(Segment::empty_literal());
@@ -123,40 +404,343 @@ pub fn lex(line: &str) -> Result<Vec<Token>> {
             }
         } else if c.is_whitespace() { // Whitespace
             if let Some(current) = current.take() {
-                tokens.push(Token::String(current));
+                tokens.push((string_token(current), current_start..start));
             }
             current = None;
-        } else { // Non-whitespace
-            if current.is_none() {
-                current = Some(vec![Segment::empty_literal()]);
+        } else if c == TILDE_CHAR && current.is_none() { // Tilde prefix
+            let mut username = String::new();
+            while let Some(c) = it.next_if(|&c| is_identifier_char(c) || c == '-' || c == '.') {
+                username.push(c);
             }
+            // Like in POSIX shells, the prefix must be followed by a separator or the end of the word
+            let is_prefix = it.peek().is_none_or(|&c| c == '/' || c == '&' || c.is_whitespace() || Operator::try_from(c.to_string().as_str()).is_ok());
+            current = Some(if is_prefix {
+                vec![Segment::empty_literal(), Segment::tilde(username)]
+            } else {
+                vec![Segment { text: format!("{}{}", TILDE_CHAR, username), kind: SegmentKind::Literal, is_quoted: false }]
+            });
+        } else { // Non-whitespace
+            let segments = current.get_or_insert_with(|| vec![Segment::empty_literal()]);
             if c == INTERPOLATION_CHAR { // Unquoted interpolation
-                current.as_mut().unwrap().push(Segment::empty_variable());
+                segments.push(lex_interpolation(&mut it)?);
+            } else if BRACE_CHARS.contains(&c) { // Unquoted brace expansion character
+                segments.push(Segment::brace(c));
+            } else if GLOB_CHARS.contains(&c) { // Unquoted glob character
+                if segments.last().unwrap().kind != SegmentKind::Glob {
+                    segments.push(Segment::empty_glob());
+                }
+                segments.last_mut().unwrap().text.push(c);
             } else {
-                current.as_mut().unwrap().last_mut().unwrap().text.push(c);
+                let last = segments.last().unwrap();
+                let continues_segment = match last.kind {
+                    SegmentKind::Literal => true,
+                    SegmentKind::Glob | SegmentKind::Brace | SegmentKind::Tilde => false,
+                    SegmentKind::Variable => is_identifier_char(c) && !last.text.starts_with(SPECIAL_VARIABLE_CHARS),
+                    SegmentKind::Parameter | SegmentKind::Command | SegmentKind::Arithmetic => false,
+                };
+                if !continues_segment {
+                    // Exiting unquoted interpolation
+                    segments.push(Segment::empty_literal());
+                }
+                segments.last_mut().unwrap().text.push(c);
             }
         }
     }
     if let Some(current) = current.take() {
-        tokens.push(Token::String(current));
+        tokens.push((string_token(current), current_start..line.len()));
+    }
+    if let Some((index, delimiter)) = pending_heredocs.first() {
+        *error_span = tokens[index - 1].1.clone();
+        bail!(IncompleteInput(format!("Expected here-document delimited by {}, but reached the end", delimiter.word)));
     }
     Ok(tokens)
 }
 
+/// The delimiter of a here-document, e.g. `EOF` in `<<EOF`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HeredocDelimiter {
+    word: String,
+    /// Whether the delimiter was (partially) quoted, in which case the body
+    /// is not interpolated.
+    is_quoted: bool,
+    /// Whether leading tabs are stripped from the body lines (`<<-`).
+    strips_tabs: bool,
+}
+
+/// Reads the delimiter of a here-document, assuming that the `<<` has already
+/// been consumed.
+fn lex_heredoc_delimiter(it: &mut Chars) -> Result<HeredocDelimiter> {
+    let strips_tabs = it.next_if_eq(&'-').is_some();
+    while it.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+    let mut word = String::new();
+    let mut is_quoted = false;
+    while let Some(c) = it.next_if(|&c| !c.is_whitespace() && Operator::try_from(c.to_string().as_str()).is_err() && c != '&') {
+        match c {
+            '\'' | '"' => {
+                is_quoted = true;
+                loop {
+                    match it.next() {
+                        Some(d) if d == c => break,
+                        Some(d) => word.push(d),
+                        None => bail!(IncompleteInput(format!("Unexpectedly reached end of {}-quoted string", c))),
+                    }
+                }
+            },
+            ESCAPE_CHAR => {
+                is_quoted = true;
+                word.extend(it.next());
+            },
+            _ => word.push(c),
+        }
+    }
+    if word.is_empty() {
+        bail!("Expected delimiter after {}", <&str>::from(Operator::Heredoc));
+    }
+    Ok(HeredocDelimiter { word, is_quoted, strips_tabs })
+}
+
+/// Reads the body of a here-document up to the line consisting of the
+/// delimiter, assuming that the line containing the `<<` has been consumed.
+/// Like double-quoted strings, the body is interpolated unless the delimiter
+/// is quoted.
+fn lex_heredoc_body(it: &mut Chars, delimiter: &HeredocDelimiter) -> Result<Vec<Segment>> {
+    let mut body = String::new();
+    loop {
+        if it.peek().is_none() {
+            bail!(IncompleteInput(format!("Expected here-document delimited by {}, but reached the end", delimiter.word)));
+        }
+        let mut line: String = it.by_ref().take_while(|&c| c != '\n').collect();
+        if delimiter.strips_tabs {
+            line = line.trim_start_matches('\t').to_owned();
+        }
+        if line == delimiter.word {
+            break;
+        }
+        body.push_str(&line);
+        body.push('\n');
+    }
+
+    if delimiter.is_quoted {
+        return Ok(vec![Segment { text: body, kind: SegmentKind::Literal, is_quoted: false }]);
+    }
+
+    let mut segments = vec![Segment::empty_literal()];
+    let mut it = Chars::new(&body);
+    while let Some(c) = it.next() {
+        let literal = if c == ESCAPE_CHAR && it.next_if_eq(&'\n').is_some() {
+            // Line continuation
+            continue;
+        } else if c == ESCAPE_CHAR && it.peek().is_some_and(|&c| c == ESCAPE_CHAR || c == INTERPOLATION_CHAR) {
+            it.next().unwrap()
+        } else if c == INTERPOLATION_CHAR {
+            let mut segment = lex_interpolation(&mut it)?;
+            segment.is_quoted = true;
+            if segment.is_open() {
+                while let Some(c) = it.next_if(|&c| is_identifier_char(c)) {
+                    segment.text.push(c);
+                }
+            }
+            if !segment.text.is_empty() || segment.kind != SegmentKind::Variable {
+                segments.push(segment);
+                continue;
+            }
+            // A lone $ is literal
+            c
+        } else {
+            c
+        };
+        if segments.last().unwrap().kind != SegmentKind::Literal {
+            segments.push(Segment::empty_literal());
+        }
+        segments.last_mut().unwrap().text.push(literal);
+    }
+    Ok(segments)
+}
+
+/// Expands history references outside of single quotes, i.e. `!!` to the
+/// last line, `!n` to the n-th line, `!-n` to the n-th last line and `!prefix`
+/// to the last line starting with the prefix. Returns `None` if the line
+/// contains no history references.
+pub fn expand_history(line: &str, history: &[String]) -> Result<Option<String>> {
+    let mut expanded = String::new();
+    let mut has_references = false;
+    let mut quote: Option<char> = None;
+    let mut it = line.chars().peekable();
+    while let Some(c) = it.next() {
+        match c {
+            ESCAPE_CHAR if quote != Some('\'') => {
+                expanded.push(c);
+                if let Some(c) = it.next() {
+                    expanded.push(c);
+                }
+            },
+            '\'' | '"' if quote.is_none() => {
+                quote = Some(c);
+                expanded.push(c);
+            },
+            '\'' | '"' if quote == Some(c) => {
+                quote = None;
+                expanded.push(c);
+            },
+            HISTORY_CHAR if quote != Some('\'') && it.peek().is_some_and(|&c| is_history_reference_char(c)) => {
+                let mut reference = String::new();
+                if let Some(c) = it.next_if_eq(&HISTORY_CHAR) {
+                    reference.push(c);
+                } else {
+                    while let Some(c) = it.next_if(|&c| is_history_reference_char(c) && c != HISTORY_CHAR) {
+                        reference.push(c);
+                    }
+                }
+                let Some(entry) = lookup_history(&reference, history) else {
+                    bail!("{}{}: event not found", HISTORY_CHAR, reference);
+                };
+                expanded.push_str(entry);
+                has_references = true;
+            },
+            _ => expanded.push(c),
+        }
+    }
+    Ok(has_references.then_some(expanded))
+}
+
+fn is_history_reference_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '=' | '\'' | '"' | '(' | ')' | ';' | '|' | '&' | '>' | '<')
+}
+
+fn lookup_history<'a>(reference: &str, history: &'a [String]) -> Option<&'a String> {
+    if reference == HISTORY_CHAR.to_string() {
+        history.last()
+    } else if let Some(Ok(n)) = reference.strip_prefix('-').map(str::parse::<usize>) {
+        history.len().checked_sub(n).and_then(|i| history.get(i))
+    } else if let Ok(n) = reference.parse::<usize>() {
+        n.checked_sub(1).and_then(|i| history.get(i))
+    } else {
+        history.iter().rev().find(|entry| entry.starts_with(reference))
+    }
+}
+
+/// Reads the longest operator starting with the given character, if any.
+/// Since all operators are at most two characters long, a single character
+/// of lookahead suffices.
+fn lex_operator(c: char, it: &mut Chars) -> Option<Operator> {
+    if let Some(&next) = it.peek() {
+        if let Ok(op) = Operator::try_from(format!("{c}{next}").as_str()) {
+            it.next();
+            return Some(op);
+        }
+    }
+    Operator::try_from(c.to_string().as_str()).ok()
+}
+
+/// Reads the source of a command substitution, assuming that the opening
+/// `$(` has already been consumed. Consumes the matching closing parenthesis,
+/// skipping over quoted strings and nested parentheses (and thus nested
+/// substitutions), which are parsed later when the substitution is parsed.
+fn lex_substitution(it: &mut Chars) -> Result<String> {
+    let mut source = String::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    loop {
+        let Some(c) = it.next() else {
+            bail!(IncompleteInput("Unexpectedly reached end of command substitution, did you close your parentheses?".to_owned()));
+        };
+        if c == ESCAPE_CHAR {
+            source.push(c);
+            if let Some(c) = it.next() {
+                source.push(c);
+            }
+            continue;
+        }
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                SUBSTITUTION_OPEN_CHAR => depth += 1,
+                SUBSTITUTION_CLOSE_CHAR if depth == 0 => return Ok(source),
+                SUBSTITUTION_CLOSE_CHAR => depth -= 1,
+                _ => {},
+            },
+        }
+        source.push(c);
+    }
+}
+
+/// Reads the text of a braced parameter expansion, assuming that the opening
+/// `${` has already been consumed. Consumes the matching closing brace,
+/// skipping over quoted strings and nested braces (e.g. in `${a:-${b}}`).
+fn lex_parameter(it: &mut Chars) -> Result<String> {
+    let mut text = String::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    loop {
+        let Some(c) = it.next() else {
+            bail!(IncompleteInput("Unexpectedly reached end of parameter expansion, did you close your braces?".to_owned()));
+        };
+        if c == ESCAPE_CHAR {
+            text.push(c);
+            if let Some(c) = it.next() {
+                text.push(c);
+            }
+            continue;
+        }
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                PARAMETER_OPEN_CHAR => depth += 1,
+                PARAMETER_CLOSE_CHAR if depth == 0 => return Ok(text),
+                PARAMETER_CLOSE_CHAR => depth -= 1,
+                _ => {},
+            },
+        }
+        text.push(c);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{assign, lex, redirect, Operator, Segment, SegmentKind, Token};
+    use super::{and, append_redirect, assign, expand_history, heredoc, input_redirect, IncompleteInput, left_paren, lex, lex_partial, lex_spanned, newline, or, pipe, quote, redirect, right_paren, semicolon, Operator, Segment, SegmentKind, Token};
 
     fn op(op: Operator) -> Token {
         Token::Operator(op)
     }
 
     fn lit(s: &str) -> Segment {
-        Segment { text: s.to_owned(), kind: SegmentKind::Literal }
+        Segment { text: s.to_owned(), kind: SegmentKind::Literal, is_quoted: false }
     }
 
     fn var(s: &str) -> Segment {
-        Segment { text: s.to_owned(), kind: SegmentKind::Variable }
+        Segment { text: s.to_owned(), kind: SegmentKind::Variable, is_quoted: false }
+    }
+
+    fn glob(s: &str) -> Segment {
+        Segment { text: s.to_owned(), kind: SegmentKind::Glob, is_quoted: false }
+    }
+
+    fn brace(c: char) -> Segment {
+        Segment { text: c.to_string(), kind: SegmentKind::Brace, is_quoted: false }
+    }
+
+    fn tilde(s: &str) -> Segment {
+        Segment { text: s.to_owned(), kind: SegmentKind::Tilde, is_quoted: false }
+    }
+
+    fn arith(s: &str) -> Segment {
+        Segment { text: s.to_owned(), kind: SegmentKind::Arithmetic, is_quoted: false }
+    }
+
+    fn param(s: &str) -> Segment {
+        Segment { text: s.to_owned(), kind: SegmentKind::Parameter, is_quoted: false }
+    }
+
+    fn cmd(s: &str) -> Segment {
+        Segment { text: s.to_owned(), kind: SegmentKind::Command, is_quoted: false }
+    }
+
+    fn quoted(segment: Segment) -> Segment {
+        Segment { is_quoted: true, ..segment }
     }
 
     fn string(s: impl IntoIterator<Item = Segment>) -> Token {
@@ -208,12 +792,35 @@ mod tests {
         assert_eq!(lex(r#"'This\\\\is a double escape'"#).unwrap(), vec![lit_string(["This\\\\is a double escape"])]);
         assert_eq!(lex(r#""Escaped dollar sign: \$test 123""#).unwrap(), vec![lit_string(["Escaped dollar sign: $test 123"])]);
         assert!(lex(r#"'Unclosed: \\\'"#).is_err());
-        // TODO: We should handle backslashes outside quoted contexts too
-        assert_eq!(lex("\\").unwrap(), vec![lit_string(["\\"])]);
+        assert!(lex("\\").unwrap_err().is::<IncompleteInput>());
+        assert_eq!(lex(r"\\").unwrap(), vec![lit_string(["\\"])]);
+        assert_eq!(lex(r"echo a\ b").unwrap(), vec![lit_string(["echo"]), lit_string(["a b"])]);
+        assert_eq!(lex(r"\$x \> a\=b \# \~ \*").unwrap(), vec![lit_string(["$x"]), lit_string([">"]), lit_string(["a=b"]), lit_string(["#"]), lit_string(["~"]), lit_string(["*"])]);
+        assert_eq!(lex(r"$x\y").unwrap(), vec![string([lit(""), var("x"), lit("y")])]);
+        assert_eq!(lex(r#""$x\y""#).unwrap(), vec![string([lit(""), quoted(var("x")), lit("y")])]);
         // TODO: Should we insert the backslash with unrecognized characters? Or error?
         assert_eq!(lex(r#"'\another char'"#).unwrap(), vec![lit_string(["another char"])]);
     }
 
+    #[test]
+    fn line_continuations() {
+        assert_eq!(lex("echo a \\\n  b").unwrap(), vec![lit_string(["echo"]), lit_string(["a"]), lit_string(["b"])]);
+        assert_eq!(lex("echo a\\\nb").unwrap(), vec![lit_string(["echo"]), lit_string(["ab"])]);
+        assert_eq!(lex("echo \"a\\\nb\"").unwrap(), vec![lit_string(["echo"]), lit_string(["ab"])]);
+        assert!(lex("ls \\").unwrap_err().is::<IncompleteInput>());
+        assert_eq!(
+            lex("cat <<EOF\na \\\nb\nEOF").unwrap(),
+            vec![lit_string(["cat"]), op(heredoc()), lit_string(["a b\n"]), op(newline())],
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        for s in ["", "a b", "it's", r#"\"$x""#, "a\\'b", "; | > ="] {
+            assert_eq!(lex(&quote(s)).unwrap(), vec![lit_string([s])]);
+        }
+    }
+
     #[test]
     fn interpolations() {
         assert_eq!(lex("$x").unwrap(), vec![string([lit(""), var("x")])]);
@@ -221,18 +828,135 @@ mod tests {
         assert_eq!(lex("test$x$y").unwrap(), vec![string([lit("test"), var("x"), var("y")])]);
         assert_eq!(lex("test $x").unwrap(), vec![lit_string(["test"]), string([lit(""), var("x")])]);
         assert_eq!(lex("'test$x'").unwrap(), vec![lit_string(["test$x"])]);
-        assert_eq!(lex(r#""$abc""#).unwrap(), vec![string([lit(""), var("abc"), lit("")])]);
-        assert_eq!(lex(r#""$abc$ghi""#).unwrap(), vec![string([lit(""), var("abc"), var("ghi"), lit("")])]);
-        assert_eq!(lex(r#""test$x""#).unwrap(), vec![string([lit("test"), var("x"), lit("")])]);
-        assert_eq!(lex(r#""$var_with_underscore abc""#).unwrap(), vec![string([lit(""), var("var_with_underscore"), lit(" abc")])]);
-        assert_eq!(lex(r#""$var_with-hyphen""#).unwrap(), vec![string([lit(""), var("var_with"), lit("-hyphen")])]);
-        assert_eq!(lex(r#""/$var_with/slash""#).unwrap(), vec![string([lit("/"), var("var_with"), lit("/slash")])]);
+        assert_eq!(lex(r#""$abc""#).unwrap(), vec![string([lit(""), quoted(var("abc")), lit("")])]);
+        assert_eq!(lex(r#""$abc$ghi""#).unwrap(), vec![string([lit(""), quoted(var("abc")), quoted(var("ghi")), lit("")])]);
+        assert_eq!(lex(r#""test$x""#).unwrap(), vec![string([lit("test"), quoted(var("x")), lit("")])]);
+        assert_eq!(lex(r#""$var_with_underscore abc""#).unwrap(), vec![string([lit(""), quoted(var("var_with_underscore")), lit(" abc")])]);
+        assert_eq!(lex(r#""$var_with-hyphen""#).unwrap(), vec![string([lit(""), quoted(var("var_with")), lit("-hyphen")])]);
+        assert_eq!(lex(r#""/$var_with/slash""#).unwrap(), vec![string([lit("/"), quoted(var("var_with")), lit("/slash")])]);
+        assert_eq!(lex("/$x/y").unwrap(), vec![string([lit("/"), var("x"), lit("/y")])]);
+        assert_eq!(lex("$x'$y'").unwrap(), vec![string([lit(""), var("x"), lit("$y")])]);
+        assert_eq!(lex("$?").unwrap(), vec![string([lit(""), var("?")])]);
+        assert_eq!(lex("$?x").unwrap(), vec![string([lit(""), var("?"), lit("x")])]);
+        assert_eq!(lex(r#""status: $?!""#).unwrap(), vec![string([lit("status: "), quoted(var("?")), lit("!")])]);
+        assert_eq!(lex(r#""\$?""#).unwrap(), vec![lit_string(["$?"])]);
+        assert_eq!(lex("$1$@ $#").unwrap(), vec![string([lit(""), var("1"), var("@")]), string([lit(""), var("#")])]);
+        assert_eq!(lex(r#""$# args: $@""#).unwrap(), vec![string([lit(""), quoted(var("#")), lit(" args: "), quoted(var("@")), lit("")])]);
+    }
+
+    #[test]
+    fn substitutions() {
+        assert_eq!(lex("$(pwd)").unwrap(), vec![string([lit(""), cmd("pwd")])]);
+        assert_eq!(lex("echo $(pwd)").unwrap(), vec![lit_string(["echo"]), string([lit(""), cmd("pwd")])]);
+        assert_eq!(lex("cd $(cat /user/me/lastdir)/a").unwrap(), vec![lit_string(["cd"]), string([lit(""), cmd("cat /user/me/lastdir"), lit("/a")])]);
+        assert_eq!(lex("$(a)$(b)").unwrap(), vec![string([lit(""), cmd("a"), cmd("b")])]);
+        assert_eq!(lex("$(echo $(pwd))").unwrap(), vec![string([lit(""), cmd("echo $(pwd)")])]);
+        assert_eq!(lex("$(echo ')' \")\")").unwrap(), vec![string([lit(""), cmd("echo ')' \")\"")])]);
+        assert_eq!(lex(r#""dir: $(pwd)!""#).unwrap(), vec![string([lit("dir: "), quoted(cmd("pwd")), lit("!")])]);
+        assert_eq!(lex(r#""$(echo "$(pwd)")""#).unwrap(), vec![string([lit(""), quoted(cmd(r#"echo "$(pwd)""#)), lit("")])]);
+        assert_eq!(lex("'$(pwd)'").unwrap(), vec![lit_string(["$(pwd)"])]);
+        assert!(lex("$(pwd").is_err());
+        assert!(lex("$(echo $(pwd)").is_err());
+        assert!(lex(r#""$(pwd""#).is_err());
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(lex("${x}").unwrap(), vec![string([lit(""), param("x")])]);
+        assert_eq!(lex("${x}_backup").unwrap(), vec![string([lit(""), param("x"), lit("_backup")])]);
+        assert_eq!(lex(r#""${x}_backup""#).unwrap(), vec![string([lit(""), quoted(param("x")), lit("_backup")])]);
+        assert_eq!(lex("${x:-a b}").unwrap(), vec![string([lit(""), param("x:-a b")])]);
+        assert_eq!(lex("${x:-${y}}/z").unwrap(), vec![string([lit(""), param("x:-${y}"), lit("/z")])]);
+        assert_eq!(lex("${x:-'}'}").unwrap(), vec![string([lit(""), param("x:-'}'")])]);
+        assert_eq!(lex("${#x} ${x%.*}").unwrap(), vec![string([lit(""), param("#x")]), string([lit(""), param("x%.*")])]);
+        assert_eq!(lex("'${x}'").unwrap(), vec![lit_string(["${x}"])]);
+        assert!(lex("${x").is_err());
+        assert!(lex(r#""${x""#).is_err());
+    }
+
+    #[test]
+    fn globs() {
+        assert_eq!(lex("*").unwrap(), vec![string([lit(""), glob("*")])]);
+        assert_eq!(lex("tmp*").unwrap(), vec![string([lit("tmp"), glob("*")])]);
+        assert_eq!(lex("a/**/b?.json").unwrap(), vec![string([lit("a/"), glob("**"), lit("/b"), glob("?"), lit(".json")])]);
+        assert_eq!(lex("[a-z]").unwrap(), vec![string([lit(""), glob("["), lit("a-z"), glob("]")])]);
+        assert_eq!(lex("$x*").unwrap(), vec![string([lit(""), var("x"), glob("*")])]);
+        assert_eq!(lex("'*'").unwrap(), vec![lit_string(["*"])]);
+        assert_eq!(lex(r#""a*"b*"#).unwrap(), vec![string([lit("a*b"), glob("*")])]);
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(lex("{a,b}").unwrap(), vec![string([lit(""), brace('{'), lit("a"), brace(','), lit("b"), brace('}')])]);
+        assert_eq!(lex("x{1..3}").unwrap(), vec![string([lit("x"), brace('{'), lit("1..3"), brace('}')])]);
+        assert_eq!(lex("'{a,b}'").unwrap(), vec![lit_string(["{a,b}"])]);
+        assert_eq!(lex("{$x,b}").unwrap(), vec![string([lit(""), brace('{'), var("x"), brace(','), lit("b"), brace('}')])]);
+    }
+
+    #[test]
+    fn tildes() {
+        assert_eq!(lex("~").unwrap(), vec![string([lit(""), tilde("")])]);
+        assert_eq!(lex("~/a").unwrap(), vec![string([lit(""), tilde(""), lit("/a")])]);
+        assert_eq!(lex("cd ~alice/b").unwrap(), vec![lit_string(["cd"]), string([lit(""), tilde("alice"), lit("/b")])]);
+        assert_eq!(lex("~;").unwrap(), vec![string([lit(""), tilde("")]), op(semicolon())]);
+        assert_eq!(lex("a~").unwrap(), vec![lit_string(["a~"])]);
+        assert_eq!(lex("~$x").unwrap(), vec![string([lit("~"), var("x")])]);
+        assert_eq!(lex("'~'").unwrap(), vec![lit_string(["~"])]);
+    }
+
+    #[test]
+    fn arithmetics() {
+        assert_eq!(lex("$((1 + 2))").unwrap(), vec![string([lit(""), arith("1 + 2")])]);
+        assert_eq!(lex("f$(((x + 1) * 2)).json").unwrap(), vec![string([lit("f"), arith("(x + 1) * 2"), lit(".json")])]);
+        assert_eq!(lex(r#""$((x))""#).unwrap(), vec![string([lit(""), quoted(arith("x")), lit("")])]);
+        assert!(lex("$((1 + 2)").is_err());
+        assert!(lex("$((1 + 2").is_err());
+    }
+
+    #[test]
+    fn spans() {
+        let spans = |line: &str| lex_spanned(line).unwrap().into_iter().map(|(_, span)| span).collect::<Vec<_>>();
+        assert_eq!(spans("echo  'a b'>x"), vec![0..4, 6..11, 11..12, 12..13]);
+        assert_eq!(spans("ls &&\n ä$x"), vec![0..2, 3..5, 5..6, 7..11]);
+        assert_eq!(spans("cat <<EOF\nx\nEOF\n"), vec![0..3, 4..6, 6..9, 9..10]);
+        assert_eq!(lex_spanned("echo 'abc").unwrap_err().to_string(), "Unexpectedly reached end of '-quoted string\n  echo 'abc\n       ^");
+        assert_eq!(lex_partial("echo 'a b").1, Some(5));
+        assert_eq!(lex_partial("echo a\"b").0.len(), 2);
+        assert_eq!(lex_partial("ls | cat <<EOF\nx").1, Some(9));
+        assert_eq!(lex_partial("ls 'a'").1, None);
+        assert_eq!(lex_spanned("ls\necho $(ls").unwrap_err().to_string(), "2:6: Unexpectedly reached end of command substitution, did you close your parentheses?\n  echo $(ls\n       ^");
+    }
+
+    #[test]
+    fn heredocs() {
+        assert_eq!(
+            lex("cat <<EOF\n{\"x\": $x}\nEOF\necho").unwrap(),
+            vec![lit_string(["cat"]), op(heredoc()), string([lit("{\"x\": "), quoted(var("x")), lit("}\n")]), op(newline()), lit_string(["echo"])],
+        );
+        assert_eq!(
+            lex("cat <<'EOF' > a\n$x \\$\nEOF").unwrap(),
+            vec![lit_string(["cat"]), op(heredoc()), lit_string(["$x \\$\n"]), op(redirect()), lit_string(["a"]), op(newline())],
+        );
+        assert_eq!(
+            lex("cat <<-END\n\ta \\$x $\n\tEND\n").unwrap(),
+            vec![lit_string(["cat"]), op(heredoc()), lit_string(["a $x $\n"]), op(newline())],
+        );
+        assert_eq!(
+            lex("cat <<A; cat <<B\na\nA\nb\nB").unwrap(),
+            vec![lit_string(["cat"]), op(heredoc()), lit_string(["a\n"]), op(semicolon()), lit_string(["cat"]), op(heredoc()), lit_string(["b\n"]), op(newline())],
+        );
+        assert_eq!(lex("cat < a").unwrap(), vec![lit_string(["cat"]), op(input_redirect()), lit_string(["a"])]);
+        assert!(lex("cat <<EOF").is_err());
+        assert!(lex("cat <<EOF\na").is_err());
+        assert!(lex("cat <<\n").is_err());
     }
 
     #[test]
     fn redirects() {
         assert_eq!(lex(">").unwrap(), vec![op(redirect())]);
-        assert_eq!(lex(">>").unwrap(), vec![op(redirect()), op(redirect())]);
+        assert_eq!(lex(">>").unwrap(), vec![op(append_redirect())]);
+        assert_eq!(lex(">>>").unwrap(), vec![op(append_redirect()), op(redirect())]);
+        assert_eq!(lex("echo 1 >>a").unwrap(), vec![lit_string(["echo"]), lit_string(["1"]), op(append_redirect()), lit_string(["a"])]);
         assert_eq!(lex(">a").unwrap(), vec![op(redirect()), lit_string(["a"])]);
         assert_eq!(lex(">1").unwrap(), vec![op(redirect()), lit_string(["1"])]);
         assert_eq!(lex("  >0>  1").unwrap(), vec![op(redirect()), lit_string(["0"]), op(redirect()), lit_string(["1"])]);
@@ -240,6 +964,67 @@ mod tests {
         assert_eq!(lex(r#"echo '{"x": 23,"y":3}' > /dev/null"#).unwrap(), vec![lit_string(["echo"]), string([r#"{"x": 23,"y":3}"#].map(lit)), op(redirect()), lit_string(["/dev/null"])])
     }
 
+    #[test]
+    fn pipes() {
+        assert_eq!(lex("|").unwrap(), vec![op(pipe())]);
+        assert_eq!(lex("ls|wc").unwrap(), vec![lit_string(["ls"]), op(pipe()), lit_string(["wc"])]);
+        assert_eq!(lex("cat a | wc -l").unwrap(), vec![lit_string(["cat"]), lit_string(["a"]), op(pipe()), lit_string(["wc"]), lit_string(["-l"])]);
+        assert_eq!(lex("echo 'a|b' \"c|d\"").unwrap(), vec![lit_string(["echo"]), lit_string(["a|b"]), lit_string(["c|d"])]);
+    }
+
+    #[test]
+    fn chains() {
+        assert_eq!(lex(";").unwrap(), vec![op(semicolon())]);
+        assert_eq!(lex("a;b").unwrap(), vec![lit_string(["a"]), op(semicolon()), lit_string(["b"])]);
+        assert_eq!(lex("a && b").unwrap(), vec![lit_string(["a"]), op(and()), lit_string(["b"])]);
+        assert_eq!(lex("a||b").unwrap(), vec![lit_string(["a"]), op(or()), lit_string(["b"])]);
+        assert_eq!(lex("a|||b").unwrap(), vec![lit_string(["a"]), op(or()), op(pipe()), lit_string(["b"])]);
+        assert_eq!(lex("a & b").unwrap(), vec![lit_string(["a"]), lit_string(["&"]), lit_string(["b"])]);
+        assert_eq!(lex("a '&&' \";\"").unwrap(), vec![lit_string(["a"]), lit_string(["&&"]), lit_string([";"])]);
+    }
+
+    #[test]
+    fn newlines() {
+        assert_eq!(lex("\n").unwrap(), vec![op(newline())]);
+        assert_eq!(lex("a\nb").unwrap(), vec![lit_string(["a"]), op(newline()), lit_string(["b"])]);
+        assert_eq!(lex("a \r\n  b").unwrap(), vec![lit_string(["a"]), op(newline()), lit_string(["b"])]);
+        assert_eq!(lex("'a\nb'").unwrap(), vec![lit_string(["a\nb"])]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(lex("# comment").unwrap(), vec![]);
+        assert_eq!(lex("#!/usr/bin/env limo\nls").unwrap(), vec![op(newline()), lit_string(["ls"])]);
+        assert_eq!(lex("ls # comment; rm x\npwd").unwrap(), vec![lit_string(["ls"]), op(newline()), lit_string(["pwd"])]);
+        assert_eq!(lex("echo a#b '#c'").unwrap(), vec![lit_string(["echo"]), lit_string(["a#b"]), lit_string(["#c"])]);
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(lex("f()").unwrap(), vec![lit_string(["f"]), op(left_paren()), op(right_paren())]);
+        assert_eq!(lex("f ( ) {").unwrap(), vec![lit_string(["f"]), op(left_paren()), op(right_paren()), lit_string(["{"])]);
+        assert_eq!(lex("'(a)'").unwrap(), vec![lit_string(["(a)"])]);
+    }
+
+    #[test]
+    fn history_expansions() {
+        let history = ["ls /", "cd /user", "echo hi"].map(|s| s.to_owned());
+        assert_eq!(expand_history("ls", &history).unwrap(), None);
+        assert_eq!(expand_history("!!", &history).unwrap(), Some("echo hi".to_owned()));
+        assert_eq!(expand_history("!! > x", &history).unwrap(), Some("echo hi > x".to_owned()));
+        assert_eq!(expand_history("!1", &history).unwrap(), Some("ls /".to_owned()));
+        assert_eq!(expand_history("!-2;!3", &history).unwrap(), Some("cd /user;echo hi".to_owned()));
+        assert_eq!(expand_history("!cd && !e", &history).unwrap(), Some("cd /user && echo hi".to_owned()));
+        assert_eq!(expand_history(r#""!!""#, &history).unwrap(), Some(r#""echo hi""#.to_owned()));
+        assert_eq!(expand_history("echo '!!' \\!! Hello world!", &history).unwrap(), None);
+        assert_eq!(expand_history("a != b", &history).unwrap(), None);
+        assert!(expand_history("!4", &history).is_err());
+        assert!(expand_history("!0", &history).is_err());
+        assert!(expand_history("!-4", &history).is_err());
+        assert!(expand_history("!rm", &history).is_err());
+        assert!(expand_history("!!", &[]).is_err());
+    }
+
     #[test]
     fn assignments() {
         assert_eq!(lex(r#"hello="123""#).unwrap(), vec![lit_string(["hello"]), op(assign()), lit_string(["123"])]);
@@ -1,14 +1,57 @@
 use anyhow::Result;
 
-use crate::context::Context;
+use crate::{context::Context, status::{ExitStatus, HandledFailure}};
 
-use self::{interpret::interpret, parse::parse};
+use self::{error::SourceError, interpret::{interpret, interpret_script}, parse::parse_spanned};
 
+pub use self::{error::IncompleteInput, interpret::call_function, lex::{expand_history, lex_partial, quote, Operator, SegmentKind, Token}, parse::{parse, StatementList, KEYWORDS}};
+
+mod arithmetic;
+mod error;
+mod glob;
 mod interpret;
 mod lex;
 mod parse;
+mod pattern;
+
+/// Prints the error to stderr. Errors that carry nothing but an exit status
+/// (e.g. from a command that already reported its failure) are not printed.
+pub fn report_error(error: &anyhow::Error) {
+    let is_bare_status = error.chain().count() == 1 && (error.is::<ExitStatus>() || error.is::<HandledFailure>());
+    if !is_bare_status {
+        eprintln!("{}", error.to_string().trim());
+    }
+}
+
+/// Parses and evaluates an arithmetic expression, e.g. for `let`.
+pub fn evaluate_arithmetic(expression: &str, ctx: &mut Context) -> Result<i64> {
+    let expression = arithmetic::parse(expression)?;
+    interpret::evaluate_arithmetic(&expression, ctx)
+}
 
+/// Parses and interprets the line, tracking its exit status in the context.
 pub async fn parse_interpret(line: &str, ctx: &mut Context) -> Result<()> {
-    let stmt = parse(line)?;
-    interpret(stmt, ctx).await
+    let list = parse(line).inspect_err(|_| ctx.status = ExitStatus::SYNTAX_ERROR)?;
+    interpret(list, ctx).await
+}
+
+/// Parses and interprets a whole script, tracking its exit status in the
+/// context. Like interactive lines, the script continues after failing
+/// statements, but fails with the status of the last one. If the script was
+/// read from a file (the origin), errors are reported at their location in it.
+pub async fn parse_interpret_script(script: &str, origin: Option<&str>, ctx: &mut Context) -> Result<()> {
+    let statements = parse_spanned(script)
+        .inspect_err(|_| ctx.status = ExitStatus::SYNTAX_ERROR)
+        .map_err(|e| match origin {
+            Some(origin) => with_origin(e, origin),
+            None => e,
+        })?;
+    interpret_script(statements, script, origin, ctx).await
+}
+
+/// Attributes a located error, e.g. a syntax error, to the file its source was
+/// read from.
+pub fn with_origin(mut error: anyhow::Error, origin: &str) -> anyhow::Error {
+    SourceError::set_origin(&mut error, origin);
+    error
 }
@@ -1,7 +1,6 @@
 use anyhow::{bail, Result};
-use multipeek::{IteratorExt, MultiPeek};
 
-use super::lex::{lex, Operator, Segment, SegmentKind, Token};
+use super::{arithmetic::{self, Expression}, error::{IncompleteInput, SourceError, Span}, lex::{lex, lex_spanned, Operator, Segment, SegmentKind, Token}};
 
 /// A fragment of an argument (a string fragment after evaluation).
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,8 +9,49 @@ pub enum Fragment {
     Literal(String),
     /// A variable substitution of the form $var
     Variable(String),
+    /// A parameter expansion of the form ${...}
+    Parameter(Parameter),
+    /// A tilde prefix of the form ~ or ~user, expanding to the home directory
+    /// of the given (or current, if empty) user.
+    Tilde(String),
+    /// Unquoted glob characters, e.g. `*`, that are expanded against the
+    /// resource tree if the argument is a pattern.
+    Glob(String),
     /// A cmd substitution of the form $(...)
-    Command(Command),
+    Command(StatementList),
+    /// An arithmetic expansion of the form $((...))
+    Arithmetic(Expression),
+    /// An unquoted substitution in the values of a for loop, whose value is
+    /// split into words at whitespace, e.g. `$(ls)` in `for f in $(ls)`.
+    Split(Box<Fragment>),
+}
+
+/// A braced parameter expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    /// The name of the expanded variable.
+    pub name: String,
+    /// The operation applied to the variable.
+    pub operation: ParameterOperation,
+}
+
+/// An operation of a parameter expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParameterOperation {
+    /// Expands to the value (`${var}`).
+    Value,
+    /// Expands to the length of the value (`${#var}`).
+    Length,
+    /// Expands to the word if the variable is unset or empty (`${var:-word}`).
+    Default(Argument),
+    /// Like `Default`, but also assigns the word to the variable (`${var:=word}`).
+    AssignDefault(Argument),
+    /// Fails with the word as message if the variable is unset or empty (`${var:?word}`).
+    Require(Argument),
+    /// Removes the shortest (`${var#pattern}`) or longest (`${var##pattern}`) matching prefix.
+    RemovePrefix { pattern: Argument, longest: bool },
+    /// Removes the shortest (`${var%pattern}`) or longest (`${var%%pattern}`) matching suffix.
+    RemoveSuffix { pattern: Argument, longest: bool },
 }
 
 /// An argument, i.e. an unevaluated string.
@@ -30,11 +70,59 @@ pub struct Assignment {
     pub rhs: Argument,
 }
 
+/// The source of a command's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    /// A local file (`< path`).
+    File(Argument),
+    /// A here-document (`<<EOF`).
+    Here(Argument),
+}
+
+/// How redirected output is written to the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectMode {
+    /// Replaces the target (`>`).
+    Replace,
+    /// Merges into the target (`>>`).
+    Merge,
+}
+
+/// How redirected output is interpreted before it is written to the target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedirectFormat {
+    /// JSON if the output parses as such, otherwise a string.
+    #[default]
+    Auto,
+    /// JSON, failing if the output does not parse.
+    Json,
+    /// MessagePack, decoded from the raw output bytes.
+    Msgpack,
+    /// A string value.
+    String,
+    /// A binary value holding the raw output bytes.
+    Bytes,
+}
+
+impl RedirectFormat {
+    const NAMES: [(&'static str, Self); 4] = [
+        ("json", Self::Json),
+        ("msgpack", Self::Msgpack),
+        ("string", Self::String),
+        ("bytes", Self::Bytes),
+    ];
+}
+
+/// The option selecting the format of a redirect, e.g. `> --as string path`.
+const REDIRECT_FORMAT_OPTION: &str = "--as";
+
 /// A command "expression".
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     Invocation { args: Vec<Argument> },
-    Redirect { inner: Box<Command>, path: Argument },
+    Input { inner: Box<Command>, source: InputSource },
+    Redirect { inner: Box<Command>, path: Argument, mode: RedirectMode, format: RedirectFormat },
+    Pipe { lhs: Box<Command>, rhs: Box<Command> },
 }
 
 /// A script statement.
@@ -42,87 +130,636 @@ pub enum Command {
 pub enum Statement {
     Assignment(Assignment),
     Command(Command),
+    /// Runs rhs only if lhs succeeds (`lhs && rhs`).
+    And { lhs: Box<Statement>, rhs: Box<Statement> },
+    /// Runs rhs only if lhs fails (`lhs || rhs`).
+    Or { lhs: Box<Statement>, rhs: Box<Statement> },
+    /// Runs then if the condition succeeds, otherwise the alternative (`if ...; then ...; else ...; fi`).
+    /// An `elif` is represented as a nested if in the alternative.
+    If { condition: StatementList, then: StatementList, otherwise: Option<StatementList> },
+    /// Runs the body once per value, binding the value to the variable (`for x in ...; do ...; done`).
+    For { variable: String, values: Vec<Argument>, body: StatementList },
+    /// Runs the body as long as the condition succeeds (`while ...; do ...; done`).
+    While { condition: StatementList, body: StatementList },
+    /// Defines a function that can be invoked like a command (`name() { ...; }`).
+    Function { name: String, body: StatementList },
+}
+
+/// A sequence of statements, separated by `;` or newlines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementList {
+    /// The statements, in order of execution.
+    pub statements: Vec<Statement>,
+}
+
+/// Reserved words that structure compound statements. Like in POSIX shells,
+/// these are only recognized at the start of a statement.
+pub const KEYWORDS: [&str; 12] = ["if", "then", "elif", "else", "fi", "for", "in", "do", "done", "while", "{", "}"];
+
+/// Keywords that end a statement list, e.g. the body of an if.
+const TERMINATOR_KEYWORDS: [&str; 7] = ["then", "elif", "else", "fi", "do", "done", "}"];
+
+/// Parses a line or a whole script, which may span multiple lines.
+pub fn parse(script: &str) -> Result<StatementList> {
+    let statements = parse_spanned(script)?.into_iter().map(|(statement, _)| statement).collect();
+    Ok(StatementList { statements })
+}
+
+/// Parses a script like `parse`, but also returns the span of each top-level
+/// statement. Errors are located at the token that could not be parsed.
+pub fn parse_spanned(script: &str) -> Result<Vec<(Statement, Span)>> {
+    let mut tokens = Tokens::new(lex_spanned(script)?, script.len());
+    let result = parse_spanned_statement_list(&mut tokens).and_then(|statements| {
+        if let Some(token) = tokens.next() {
+            bail!("Parse error: Unexpected {}", token);
+        }
+        Ok(statements)
+    });
+    result.map_err(|e| SourceError::locate(e, script, tokens.span.clone()))
+}
+
+/// The tokens of a line or script. Keeps track of the span of the token
+/// looked at last, which is where parse errors are reported.
+struct Tokens {
+    tokens: Vec<(Token, Span)>,
+    index: usize,
+    /// The span of the token looked at last (or the end of the source).
+    span: Span,
+    /// The end of the last consumed token.
+    consumed_end: usize,
+    source_len: usize,
+}
+
+impl Tokens {
+    fn new(tokens: Vec<(Token, Span)>, source_len: usize) -> Self {
+        Self { tokens, index: 0, span: 0..0, consumed_end: 0, source_len }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        match self.tokens.get(self.index + n) {
+            Some((token, span)) => {
+                self.span = span.clone();
+                Some(token)
+            },
+            None => {
+                self.span = self.source_len..self.source_len;
+                None
+            },
+        }
+    }
+
+    /// The last consumed token, if any.
+    fn previous(&self) -> Option<&Token> {
+        self.index.checked_sub(1).map(|i| &self.tokens[i].0)
+    }
+
+    /// Whether the n-th next token directly follows the token before it,
+    /// i.e. without whitespace in between.
+    fn is_adjacent(&self, n: usize) -> bool {
+        let i = self.index + n;
+        i > 0 && i < self.tokens.len() && self.tokens[i - 1].1.end == self.tokens[i].1.start
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.peek()?;
+        let (token, span) = self.tokens[self.index].clone();
+        self.index += 1;
+        self.consumed_end = span.end;
+        Some(token)
+    }
 }
 
-pub fn parse(line: &str) -> Result<Statement> {
-    Result::<Statement>::from_iter(lex(line)?)
+fn parse_statement_list(tokens: &mut Tokens) -> Result<StatementList> {
+    let statements = parse_spanned_statement_list(tokens)?.into_iter().map(|(statement, _)| statement).collect();
+    Ok(StatementList { statements })
 }
 
-impl FromIterator<Token> for Result<Statement> {
-    fn from_iter<T>(iter: T) -> Result<Statement> where T: IntoIterator<Item = Token> {
-        parse_statement(&mut iter.into_iter().multipeek())
-        
+fn parse_spanned_statement_list(tokens: &mut Tokens) -> Result<Vec<(Statement, Span)>> {
+    let mut statements = Vec::new();
+    loop {
+        if peek_keyword(tokens).is_some_and(|k| TERMINATOR_KEYWORDS.contains(&k)) {
+            break;
+        }
+        tokens.peek();
+        let start = tokens.span.start;
+        let statement = parse_chain(tokens)?;
+        // Empty statements, e.g. from blank lines or a trailing ;, are skipped
+        if !is_empty_statement(&statement) {
+            statements.push((statement, start..tokens.consumed_end));
+        }
+        let Some(Token::Operator(Operator::Semicolon | Operator::Newline)) = tokens.peek() else {
+            break;
+        };
+        tokens.next();
     }
+    Ok(statements)
+}
+
+/// The keyword at the start of the remaining tokens, if any.
+fn peek_keyword(tokens: &mut Tokens) -> Option<&'static str> {
+    let Some(Token::String(segments)) = tokens.peek() else {
+        return None;
+    };
+    let [Segment { text, kind: SegmentKind::Literal, .. }] = segments.as_slice() else {
+        return None;
+    };
+    KEYWORDS.into_iter().find(|&k| k == text)
+}
+
+fn expect_keyword(tokens: &mut Tokens, keyword: &str, context: &str) -> Result<()> {
+    if peek_keyword(tokens) != Some(keyword) {
+        match tokens.peek() {
+            Some(token) => bail!("Parse error: Expected {} in {}, but got {}", keyword, context, token),
+            None => bail!(IncompleteInput(format!("Parse error: Expected {} in {}, but reached the end", keyword, context))),
+        }
+    }
+    tokens.next();
+    Ok(())
+}
+
+fn skip_newlines(tokens: &mut Tokens) {
+    while let Some(Token::Operator(Operator::Newline)) = tokens.peek() {
+        tokens.next();
+    }
+}
+
+fn parse_if(tokens: &mut Tokens) -> Result<Statement> {
+    // Consume the if (or elif)
+    tokens.next();
+    let condition = parse_statement_list(tokens)?;
+    if condition.statements.is_empty() {
+        bail!("Parse error: Expected condition after if");
+    }
+    expect_keyword(tokens, "then", "if")?;
+    let then = parse_statement_list(tokens)?;
+    let otherwise = match peek_keyword(tokens) {
+        Some("elif") => Some(StatementList { statements: vec![parse_if(tokens)?] }),
+        Some("else") => {
+            tokens.next();
+            let otherwise = parse_statement_list(tokens)?;
+            expect_keyword(tokens, "fi", "if")?;
+            Some(otherwise)
+        },
+        _ => {
+            expect_keyword(tokens, "fi", "if")?;
+            None
+        },
+    };
+    Ok(Statement::If { condition, then, otherwise })
 }
 
-fn parse_statement<T>(tokens: &mut MultiPeek<T>) -> Result<Statement> where T: Iterator<Item = Token> {
-    parse_assignment(tokens)
-        .map(|a| Statement::Assignment(a))
-        .or_else(|_| parse_command(tokens).map(|c| Statement::Command(c)))
+fn parse_for(tokens: &mut Tokens) -> Result<Statement> {
+    // Consume the for
+    tokens.next();
+    let variable = parse_name(tokens, "for")?;
+    expect_keyword(tokens, "in", "for")?;
+    let mut values = Vec::new();
+    while let Some(Token::String(segments)) = tokens.peek().cloned() {
+        tokens.next();
+        values.extend(parse_split_words(&segments)?);
+    }
+    let Some(Token::Operator(Operator::Semicolon | Operator::Newline)) = tokens.next() else {
+        bail!("Parse error: Expected ; or newline after the values of for");
+    };
+    skip_newlines(tokens);
+    let body = parse_loop_body(tokens, "for")?;
+    Ok(Statement::For { variable, values, body })
+}
+
+fn parse_while(tokens: &mut Tokens) -> Result<Statement> {
+    // Consume the while
+    tokens.next();
+    let condition = parse_statement_list(tokens)?;
+    if condition.statements.is_empty() {
+        bail!("Parse error: Expected condition after while");
+    }
+    let body = parse_loop_body(tokens, "while")?;
+    Ok(Statement::While { condition, body })
+}
+
+fn parse_function(tokens: &mut Tokens) -> Result<Statement> {
+    let name = parse_name(tokens, "function definition")?;
+    let (Some(Token::Operator(Operator::LeftParen)), Some(Token::Operator(Operator::RightParen))) = (tokens.next(), tokens.next()) else {
+        bail!("Parse error: Expected () after the name of function {}", name);
+    };
+    skip_newlines(tokens);
+    expect_keyword(tokens, "{", "function definition")?;
+    let body = parse_statement_list(tokens)?;
+    expect_keyword(tokens, "}", "function definition")?;
+    Ok(Statement::Function { name, body })
+}
+
+/// Parses a literal name, e.g. of a variable or function.
+fn parse_name(tokens: &mut Tokens, context: &str) -> Result<String> {
+    match tokens.next() {
+        Some(Token::String(segments)) => match segments.as_slice() {
+            [Segment { text, kind: SegmentKind::Literal, .. }] if !text.is_empty() => Ok(text.clone()),
+            _ => bail!("Parse error: Expected a literal name in {}", context),
+        },
+        _ => bail!("Parse error: Expected name in {}", context),
+    }
+}
+
+fn parse_loop_body(tokens: &mut Tokens, context: &str) -> Result<StatementList> {
+    expect_keyword(tokens, "do", context)?;
+    let body = parse_statement_list(tokens)?;
+    expect_keyword(tokens, "done", context)?;
+    Ok(body)
+}
+
+fn parse_chain(tokens: &mut Tokens) -> Result<Statement> {
+    let mut statement = parse_statement(tokens)?;
+
+    while let Some(Token::Operator(op @ (Operator::And | Operator::Or))) = tokens.peek().cloned() {
+        tokens.next();
+        skip_newlines(tokens);
+        let rhs = parse_statement(tokens)?;
+        if !is_empty_statement(&statement) && is_empty_statement(&rhs) && tokens.peek().is_none() {
+            bail!(IncompleteInput(format!("Parse error: Expected statement after {}", <&str>::from(op))));
+        }
+        if is_empty_statement(&statement) || is_empty_statement(&rhs) {
+            bail!("Parse error: Expected statement on both sides of {}", <&str>::from(op));
+        }
+        let [lhs, rhs] = [statement, rhs].map(Box::new);
+        statement = match op {
+            Operator::And => Statement::And { lhs, rhs },
+            _ => Statement::Or { lhs, rhs },
+        };
+    }
+
+    Ok(statement)
 }
 
-fn parse_assignment<T>(tokens: &mut MultiPeek<T>) -> Result<Assignment> where T: Iterator<Item = Token> {
-    let Some(Token::String(lhs)) = tokens.peek_nth(0).cloned() else {
+fn parse_statement(tokens: &mut Tokens) -> Result<Statement> {
+    match peek_keyword(tokens) {
+        Some("if") => return parse_if(tokens),
+        Some("for") => return parse_for(tokens),
+        Some("while") => return parse_while(tokens),
+        _ => {},
+    }
+    if let Some(Token::Operator(Operator::LeftParen)) = tokens.peek_nth(1) {
+        return parse_function(tokens);
+    }
+    if let Some(Token::Operator(Operator::Assign)) = tokens.peek_nth(1) {
+        parse_assignment(tokens).map(Statement::Assignment)
+    } else {
+        parse_command(tokens).map(Statement::Command)
+    }
+}
+
+fn is_empty_statement(statement: &Statement) -> bool {
+    matches!(statement, Statement::Command(command) if is_empty_invocation(command))
+}
+
+fn parse_assignment(tokens: &mut Tokens) -> Result<Assignment> {
+    let Some(Token::String(lhs)) = tokens.next() else {
         bail!("Parse error: Expected variable name in assignment");
     };
-    let Some(Token::Operator(Operator::Assign)) = tokens.peek_nth(1) else {
+    let Some(Token::Operator(Operator::Assign)) = tokens.next() else {
         bail!("Parse error: Expected operator (=) in assignment");
     };
-    let Some(Token::String(rhs)) = tokens.peek_nth(2).cloned() else {
-        bail!("Parse error: Expected variable name in assignment");
+    let Some(Token::String(rhs)) = tokens.next() else {
+        bail!("Parse error: Expected value in assignment");
     };
+    if let Some(Token::String(_)) = tokens.peek() {
+        bail!("Parse error: Unexpected argument after assignment, did you quote the value?");
+    }
     let lhs = parse_argument(&lhs)?;
     let rhs = parse_argument(&rhs)?;
     Ok(Assignment { lhs, rhs })
 }
 
-fn parse_command<T>(tokens: &mut MultiPeek<T>) -> Result<Command> where T: Iterator<Item = Token> {
+fn parse_command(tokens: &mut Tokens) -> Result<Command> {
+    let mut command = parse_redirected_invocation(tokens)?;
+
+    while let Some(Token::Operator(Operator::Pipe)) = tokens.peek() {
+        tokens.next();
+        skip_newlines(tokens);
+        let rhs = parse_redirected_invocation(tokens)?;
+        if !is_empty_invocation(&command) && is_empty_invocation(&rhs) && tokens.peek().is_none() {
+            bail!(IncompleteInput("Parse error: Expected command after pipe (|)".to_owned()));
+        }
+        if is_empty_invocation(&command) || is_empty_invocation(&rhs) {
+            bail!("Parse error: Expected command on both sides of pipe (|)");
+        }
+        command = Command::Pipe { lhs: Box::new(command), rhs: Box::new(rhs) };
+    }
+
+    Ok(command)
+}
+
+fn is_empty_invocation(command: &Command) -> bool {
+    matches!(command, Command::Invocation { args } if args.is_empty())
+}
+
+fn parse_redirected_invocation(tokens: &mut Tokens) -> Result<Command> {
     let mut args = Vec::<Argument>::new();
-    let mut redirects = Vec::<Argument>::new();
-    let mut in_redirect = false;
+    let mut redirects = Vec::<(Argument, RedirectMode, RedirectFormat)>::new();
+    let mut input = None;
+    let mut redirect_mode = None;
+    let mut redirect_format = RedirectFormat::default();
 
     while let Some(token) = tokens.peek().cloned() {
         match token {
             Token::String(arg) => {
                 tokens.next();
-                let arg = parse_argument(&arg)?;
-                if in_redirect {
-                    redirects.push(arg);
+                if let Some(mode) = redirect_mode {
+                    let path = parse_argument(&arg)?;
+                    if path.fragments == [Fragment::Literal(REDIRECT_FORMAT_OPTION.to_owned())] {
+                        redirect_format = parse_redirect_format(tokens)?;
+                    } else {
+                        redirects.push((path, mode, redirect_format));
+                        redirect_format = RedirectFormat::default();
+                    }
                 } else {
-                    args.push(arg);
+                    args.extend(parse_words(&arg)?);
                 }
             },
             Token::Operator(Operator::Redirect) => {
                 tokens.next();
-                in_redirect = true
+                redirect_mode = Some(RedirectMode::Replace);
             },
-            _ => bail!("Parse error: Unexpected {:?} in invocation, did you close your quotes?", token)
+            Token::Operator(Operator::AppendRedirect) => {
+                tokens.next();
+                redirect_mode = Some(RedirectMode::Merge);
+            },
+            Token::Operator(ref op @ (Operator::InputRedirect | Operator::Heredoc)) => {
+                tokens.next();
+                let Some(Token::String(source)) = tokens.next() else {
+                    bail!("Parse error: Expected {} after {}", if *op == Operator::Heredoc { "delimiter" } else { "file" }, token);
+                };
+                // Like in POSIX shells, the last input takes precedence
+                let source = parse_argument(&source)?;
+                input = Some(if *op == Operator::Heredoc { InputSource::Here(source) } else { InputSource::File(source) });
+            },
+            Token::Operator(Operator::Assign) => {
+                // Within an invocation, the = is part of the words it touches
+                // (e.g. `alias a=b`), otherwise it is an argument of its own
+                let joins_previous = matches!(tokens.previous(), Some(Token::String(_))) && tokens.is_adjacent(0);
+                tokens.next();
+                let mut fragments = vec![Fragment::Literal(<&str>::from(Operator::Assign).to_owned())];
+                if tokens.is_adjacent(0) {
+                    if let Some(Token::String(rhs)) = tokens.peek().cloned() {
+                        tokens.next();
+                        fragments.extend(parse_argument(&rhs)?.fragments);
+                    }
+                }
+                if !joins_previous && redirect_mode.is_none() && !args.is_empty() {
+                    args.push(Argument { fragments });
+                } else {
+                    let target = if redirect_mode.is_some() { redirects.last_mut().map(|(path, _, _)| path) } else { args.last_mut() };
+                    let Some(arg) = target.filter(|_| joins_previous) else {
+                        bail!("Parse error: Unexpected {} in invocation, did you close your quotes?", token);
+                    };
+                    arg.fragments.extend(fragments);
+                }
+            },
+            Token::Operator(_) => break,
         }
     }
 
+    let mut command = Command::Invocation { args };
+    if let Some(source) = input {
+        command = Command::Input { inner: Box::new(command), source };
+    }
+
     Ok(redirects.into_iter().fold(
-        Command::Invocation { args },
-        |inner, path| {
-            Command::Redirect { inner: Box::new(inner), path }
+        command,
+        |inner, (path, mode, format)| {
+            Command::Redirect { inner: Box::new(inner), path, mode, format }
         }
     ))
 }
 
+/// Parses the format of a redirect, assuming that the option has already been
+/// consumed.
+fn parse_redirect_format(tokens: &mut Tokens) -> Result<RedirectFormat> {
+    let names = || RedirectFormat::NAMES.map(|(name, _)| name).join(", ");
+    let Some(Token::String(segments)) = tokens.next() else {
+        bail!("Parse error: Expected format after {} (one of {})", REDIRECT_FORMAT_OPTION, names());
+    };
+    let name: String = segments.iter().map(|s| s.text.as_str()).collect();
+    match RedirectFormat::NAMES.into_iter().find(|(n, _)| *n == name) {
+        Some((_, format)) => Ok(format),
+        None => bail!("Parse error: Unknown redirect format {} (expected one of {})", name, names()),
+    }
+}
+
+/// Parses a word into arguments, performing brace expansion.
+fn parse_words(segments: &[Segment]) -> Result<Vec<Argument>> {
+    expand_braces(segments).iter()
+        .map(|segments| parse_argument(segments))
+        .collect()
+}
+
+/// Expands unquoted braces in the segments of a word, e.g. `a{b,c}` to `ab`
+/// and `ac` or `{1..3}` to `1`, `2` and `3`. Like in POSIX shells, braces
+/// without a comma or sequence are kept literally.
+fn expand_braces(segments: &[Segment]) -> Vec<Vec<Segment>> {
+    let is_brace = |segment: &Segment, c: &str| segment.kind == SegmentKind::Brace && segment.text == c;
+
+    for (open, _) in segments.iter().enumerate().filter(|(_, s)| is_brace(s, "{")) {
+        let mut depth = 0usize;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (i, segment) in segments.iter().enumerate().skip(open + 1) {
+            if is_brace(segment, "{") {
+                depth += 1;
+            } else if is_brace(segment, "}") && depth > 0 {
+                depth -= 1;
+            } else if is_brace(segment, "}") {
+                close = Some(i);
+                break;
+            } else if is_brace(segment, ",") && depth == 0 {
+                commas.push(i);
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+
+        let alternatives: Vec<Vec<Segment>> = if commas.is_empty() {
+            let inner = &segments[(open + 1)..close];
+            let [Segment { text, kind: SegmentKind::Literal, .. }] = inner else {
+                continue;
+            };
+            let Some(sequence) = expand_sequence(text) else {
+                continue;
+            };
+            sequence.into_iter()
+                .map(|text| vec![Segment { text, kind: SegmentKind::Literal, is_quoted: false }])
+                .collect()
+        } else {
+            [open].into_iter().chain(commas.iter().copied())
+                .zip(commas.iter().copied().chain([close]))
+                .map(|(start, end)| segments[(start + 1)..end].to_vec())
+                .collect()
+        };
+
+        return alternatives.into_iter()
+            .flat_map(|alternative| {
+                let expanded = [&segments[..open], &alternative, &segments[(close + 1)..]].concat();
+                expand_braces(&expanded)
+            })
+            .collect();
+    }
+
+    vec![segments.to_vec()]
+}
+
+/// Expands a brace sequence of the form `x..y` or `x..y..step` over integers
+/// (zero-padded if either bound is) or single letters.
+fn expand_sequence(text: &str) -> Option<Vec<String>> {
+    let (start, end, step) = match *text.split("..").collect::<Vec<_>>().as_slice() {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.unsigned_abs().max(1) as i64),
+        _ => return None,
+    };
+    let range = |start: i64, end: i64| {
+        let count = (end - start).abs() / step;
+        (0..=count).map(move |i| start + i * step * (end - start).signum())
+    };
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let is_padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if is_padded(start) || is_padded(end) { start.len().max(end.len()) } else { 0 };
+        return Some(range(first, last).map(|n| format!("{:0width$}", n)).collect());
+    }
+
+    match (start.chars().collect::<Vec<_>>().as_slice(), end.chars().collect::<Vec<_>>().as_slice()) {
+        (&[first], &[last]) if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() => Some(
+            range(first as i64, last as i64)
+                .filter_map(|c| char::from_u32(c as u32))
+                .map(String::from)
+                .collect()
+        ),
+        _ => None,
+    }
+}
+
+/// Parses a word into arguments like `parse_words`, but marks unquoted
+/// substitutions for word splitting, like in POSIX shells.
+fn parse_split_words(segments: &[Segment]) -> Result<Vec<Argument>> {
+    expand_braces(segments).iter()
+        .map(|segments| parse_fragments(segments, true))
+        .collect()
+}
+
 fn parse_argument(segments: &[Segment]) -> Result<Argument> {
-    let fragments = segments.into_iter().map(parse_segment).collect();
+    parse_fragments(segments, false)
+}
+
+fn parse_fragments(segments: &[Segment], splits_unquoted: bool) -> Result<Argument> {
+    let mut fragments = Vec::<Fragment>::new();
+    for segment in segments {
+        let fragment = match parse_segment(segment)? {
+            fragment @ (Fragment::Variable(_) | Fragment::Parameter(_) | Fragment::Command(_)) if splits_unquoted && !segment.is_quoted => {
+                Fragment::Split(Box::new(fragment))
+            },
+            fragment => fragment,
+        };
+        // Merge literals, e.g. from unexpanded braces
+        match (fragments.last_mut(), fragment) {
+            (Some(Fragment::Literal(last)), Fragment::Literal(literal)) => last.push_str(&literal),
+            (_, fragment) => fragments.push(fragment),
+        }
+    }
     Ok(Argument { fragments })
 }
 
-fn parse_segment(segment: &Segment) -> Fragment {
-    match segment.kind {
+fn parse_segment(segment: &Segment) -> Result<Fragment> {
+    Ok(match segment.kind {
         SegmentKind::Literal => Fragment::Literal(segment.text.to_owned()),
         SegmentKind::Variable => Fragment::Variable(segment.text.to_owned()),
+        SegmentKind::Brace => Fragment::Literal(segment.text.to_owned()),
+        SegmentKind::Tilde => Fragment::Tilde(segment.text.to_owned()),
+        SegmentKind::Glob => Fragment::Glob(segment.text.to_owned()),
+        SegmentKind::Parameter => Fragment::Parameter(parse_parameter(&segment.text)?),
+        SegmentKind::Command => Fragment::Command(parse(&segment.text)?),
+        SegmentKind::Arithmetic => Fragment::Arithmetic(arithmetic::parse(&segment.text)?),
+    })
+}
+
+/// Parses the text of a braced parameter expansion, i.e. between the braces.
+fn parse_parameter(text: &str) -> Result<Parameter> {
+    if let Some(name) = text.strip_prefix('#').filter(|name| !name.is_empty()) {
+        if !is_parameter_name(name) {
+            bail!("Parse error: Bad substitution: ${{{}}}", text);
+        }
+        return Ok(Parameter { name: name.to_owned(), operation: ParameterOperation::Length });
     }
+
+    let name_len = if text.starts_with(['?', '@', '#']) {
+        1
+    } else {
+        text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(text.len())
+    };
+    let (name, rest) = text.split_at(name_len);
+    if !is_parameter_name(name) {
+        bail!("Parse error: Bad substitution: ${{{}}}", text);
+    }
+
+    let operation = if rest.is_empty() {
+        ParameterOperation::Value
+    } else if let Some(word) = rest.strip_prefix(":-") {
+        ParameterOperation::Default(parse_word(word)?)
+    } else if let Some(word) = rest.strip_prefix(":=") {
+        ParameterOperation::AssignDefault(parse_word(word)?)
+    } else if let Some(word) = rest.strip_prefix(":?") {
+        ParameterOperation::Require(parse_word(word)?)
+    } else if let Some(pattern) = rest.strip_prefix("##") {
+        ParameterOperation::RemovePrefix { pattern: parse_word(pattern)?, longest: true }
+    } else if let Some(pattern) = rest.strip_prefix('#') {
+        ParameterOperation::RemovePrefix { pattern: parse_word(pattern)?, longest: false }
+    } else if let Some(pattern) = rest.strip_prefix("%%") {
+        ParameterOperation::RemoveSuffix { pattern: parse_word(pattern)?, longest: true }
+    } else if let Some(pattern) = rest.strip_prefix('%') {
+        ParameterOperation::RemoveSuffix { pattern: parse_word(pattern)?, longest: false }
+    } else {
+        bail!("Parse error: Bad substitution: ${{{}}}", text);
+    };
+
+    Ok(Parameter { name: name.to_owned(), operation })
+}
+
+fn is_parameter_name(name: &str) -> bool {
+    let is_positional = !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+    let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    matches!(name, "?" | "@" | "#") || is_positional || is_identifier
+}
+
+/// Parses the word of a parameter expansion (e.g. the default value) into a
+/// single argument. Whitespace between unquoted parts is kept as a single space.
+fn parse_word(word: &str) -> Result<Argument> {
+    let mut fragments = Vec::new();
+    let mut follows_string = false;
+    for token in lex(word)? {
+        match token {
+            Token::String(segments) => {
+                if follows_string {
+                    fragments.push(Fragment::Literal(" ".to_owned()));
+                }
+                fragments.extend(parse_argument(&segments)?.fragments);
+                follows_string = true;
+            },
+            Token::Operator(op @ Operator::Assign) => {
+                fragments.push(Fragment::Literal(<&str>::from(op).to_owned()));
+                follows_string = false;
+            },
+            Token::Operator(op) => bail!("Parse error: Unexpected {} in parameter expansion", op),
+        }
+    }
+    Ok(Argument { fragments })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse, Argument, Command, Fragment, Statement};
+    use super::{parse, parse_spanned, Argument, Assignment, Command, Fragment, IncompleteInput, InputSource, Parameter, ParameterOperation, RedirectFormat, RedirectMode, Statement, StatementList};
 
     fn lit(value: &str) -> Fragment {
         Fragment::Literal(value.to_owned())
@@ -137,7 +774,23 @@ mod tests {
     }
 
     fn redirect(command: Command, path: Argument) -> Command {
-        Command::Redirect { inner: Box::new(command), path }
+        Command::Redirect { inner: Box::new(command), path, mode: RedirectMode::Replace, format: RedirectFormat::Auto }
+    }
+
+    fn merge_redirect(command: Command, path: &str) -> Command {
+        Command::Redirect { inner: Box::new(command), path: arg([lit(path)]), mode: RedirectMode::Merge, format: RedirectFormat::Auto }
+    }
+
+    fn formatted_redirect(command: Command, path: &str, format: RedirectFormat) -> Command {
+        Command::Redirect { inner: Box::new(command), path: arg([lit(path)]), mode: RedirectMode::Replace, format }
+    }
+
+    fn input(command: Command, source: InputSource) -> Command {
+        Command::Input { inner: Box::new(command), source }
+    }
+
+    fn pipe(lhs: Command, rhs: Command) -> Command {
+        Command::Pipe { lhs: Box::new(lhs), rhs: Box::new(rhs) }
     }
 
     fn lit_invocation(lits: impl IntoIterator<Item = &'static str>) -> Command {
@@ -152,52 +805,434 @@ mod tests {
         Statement::Command(command)
     }
 
-    // TODO: Add tests for variable interpolations
+    fn list(statements: impl IntoIterator<Item = Statement>) -> StatementList {
+        StatementList { statements: statements.into_iter().collect() }
+    }
+
+    fn cmd_list(command: Command) -> StatementList {
+        list([cmd_stmt(command)])
+    }
+
+    fn and(lhs: Statement, rhs: Statement) -> Statement {
+        Statement::And { lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+
+    fn or(lhs: Statement, rhs: Statement) -> Statement {
+        Statement::Or { lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+
+    fn if_(condition: StatementList, then: StatementList, otherwise: Option<StatementList>) -> Statement {
+        Statement::If { condition, then, otherwise }
+    }
+
+    fn for_<const N: usize>(variable: &str, values: [&str; N], body: StatementList) -> Statement {
+        Statement::For { variable: variable.to_owned(), values: values.into_iter().map(|v| arg([lit(v)])).collect(), body }
+    }
+
+    fn while_(condition: StatementList, body: StatementList) -> Statement {
+        Statement::While { condition, body }
+    }
+
+    fn function(name: &str, body: StatementList) -> Statement {
+        Statement::Function { name: name.to_owned(), body }
+    }
+
+    fn assign(lhs: &str, rhs: &str) -> Statement {
+        Statement::Assignment(Assignment { lhs: arg([lit(lhs)]), rhs: arg([lit(rhs)]) })
+    }
+
+    fn var(name: &str) -> Fragment {
+        Fragment::Variable(name.to_owned())
+    }
+
+    fn sub(command: Command) -> Fragment {
+        Fragment::Command(cmd_list(command))
+    }
 
     #[test]
     fn whitespace() {
-        assert_eq!(parse("").unwrap(), cmd_stmt(lit_invocation([])));
-        assert_eq!(parse("  ").unwrap(), cmd_stmt(lit_invocation([])));
+        assert_eq!(parse("").unwrap(), list([]));
+        assert_eq!(parse("  ").unwrap(), list([]));
     }
 
     #[test]
     fn simple_commands() {
-        assert_eq!(parse("echo").unwrap(), cmd_stmt(lit_invocation(["echo"])));
-        assert_eq!(parse("echo 123").unwrap(), cmd_stmt(lit_invocation(["echo", "123"])));
-        assert_eq!(parse("echo \"123\"").unwrap(), cmd_stmt(lit_invocation(["echo", "123"])));
-        assert_eq!(parse("ls -la").unwrap(), cmd_stmt(lit_invocation(["ls", "-la"])));
-        assert_eq!(parse("  pwd  --help ").unwrap(), cmd_stmt(lit_invocation(["pwd", "--help"])));
-        assert_eq!(parse("pwd--help").unwrap(), cmd_stmt(lit_invocation(["pwd--help"])));
-        assert_eq!(parse("cd..").unwrap(), cmd_stmt(lit_invocation(["cd.."])));
-        assert_eq!(parse("cd ..").unwrap(), cmd_stmt(lit_invocation(["cd", ".."])));
-        assert_eq!(parse("cd ./test/../../.").unwrap(), cmd_stmt(lit_invocation(["cd", "./test/../../."])));
+        assert_eq!(parse("echo").unwrap(), cmd_list(lit_invocation(["echo"])));
+        assert_eq!(parse("echo 123").unwrap(), cmd_list(lit_invocation(["echo", "123"])));
+        assert_eq!(parse("echo \"123\"").unwrap(), cmd_list(lit_invocation(["echo", "123"])));
+        assert_eq!(parse("ls -la").unwrap(), cmd_list(lit_invocation(["ls", "-la"])));
+        assert_eq!(parse("  pwd  --help ").unwrap(), cmd_list(lit_invocation(["pwd", "--help"])));
+        assert_eq!(parse("pwd--help").unwrap(), cmd_list(lit_invocation(["pwd--help"])));
+        assert_eq!(parse("cd..").unwrap(), cmd_list(lit_invocation(["cd.."])));
+        assert_eq!(parse("cd ..").unwrap(), cmd_list(lit_invocation(["cd", ".."])));
+        assert_eq!(parse("cd ./test/../../.").unwrap(), cmd_list(lit_invocation(["cd", "./test/../../."])));
     }
 
     #[test]
     fn redirects() {
         assert_eq!(
             parse("cat hello >123").unwrap(),
-            cmd_stmt(lit_redirect(lit_invocation(["cat", "hello"]), "123"))
+            cmd_list(lit_redirect(lit_invocation(["cat", "hello"]), "123"))
         );
         assert_eq!(
             parse("echo 123   > /dev/null").unwrap(),
-            cmd_stmt(lit_redirect(lit_invocation(["echo", "123"]), "/dev/null"))
+            cmd_list(lit_redirect(lit_invocation(["echo", "123"]), "/dev/null"))
         );
         assert_eq!(
             parse("\"\">a/b").unwrap(),
-            cmd_stmt(lit_redirect(lit_invocation([""]), "a/b"))
+            cmd_list(lit_redirect(lit_invocation([""]), "a/b"))
         );
         assert_eq!(
             parse("\"\">a/b>c/d").unwrap(),
-            cmd_stmt(lit_redirect(lit_redirect(lit_invocation([""]), "a/b"), "c/d"))
+            cmd_list(lit_redirect(lit_redirect(lit_invocation([""]), "a/b"), "c/d"))
         );
         assert_eq!(
             parse("\"\">a/b  > c/d").unwrap(),
-            cmd_stmt(lit_redirect(lit_redirect(lit_invocation([""]), "a/b"), "c/d"))
+            cmd_list(lit_redirect(lit_redirect(lit_invocation([""]), "a/b"), "c/d"))
         );
         assert_eq!(
             parse(r#"echo '{"x": 23,"y":3}' > /dev/null"#).unwrap(),
-            cmd_stmt(lit_redirect(lit_invocation(["echo", "{\"x\": 23,\"y\":3}"]), "/dev/null"))
+            cmd_list(lit_redirect(lit_invocation(["echo", "{\"x\": 23,\"y\":3}"]), "/dev/null"))
+        );
+        assert_eq!(
+            parse("echo '[1]' >> a").unwrap(),
+            cmd_list(merge_redirect(lit_invocation(["echo", "[1]"]), "a"))
+        );
+        assert_eq!(
+            parse("echo 1 >a >>b").unwrap(),
+            cmd_list(merge_redirect(lit_redirect(lit_invocation(["echo", "1"]), "a"), "b"))
+        );
+    }
+
+    #[test]
+    fn redirect_formats() {
+        assert_eq!(
+            parse("echo hello > --as string greeting").unwrap(),
+            cmd_list(formatted_redirect(lit_invocation(["echo", "hello"]), "greeting", RedirectFormat::String))
+        );
+        assert_eq!(
+            parse("echo 1 >--as bytes a > b").unwrap(),
+            cmd_list(lit_redirect(formatted_redirect(lit_invocation(["echo", "1"]), "a", RedirectFormat::Bytes), "b"))
+        );
+        assert!(parse("echo 1 > --as yaml a").is_err());
+        assert!(parse("echo 1 > --as").is_err());
+    }
+
+    #[test]
+    fn inputs() {
+        assert_eq!(
+            parse("cat < a.json").unwrap(),
+            cmd_list(input(lit_invocation(["cat"]), InputSource::File(arg([lit("a.json")]))))
+        );
+        assert_eq!(
+            parse("cat <a <b >c").unwrap(),
+            cmd_list(lit_redirect(input(lit_invocation(["cat"]), InputSource::File(arg([lit("b")]))), "c"))
+        );
+        assert_eq!(
+            parse("cat <<EOF > a\n{\"x\": $x}\nEOF\n").unwrap(),
+            cmd_list(lit_redirect(
+                input(lit_invocation(["cat"]), InputSource::Here(arg([lit("{\"x\": "), var("x"), lit("}\n")]))),
+                "a",
+            ))
+        );
+        assert!(parse("cat <").is_err());
+        assert!(parse("cat < | wc").is_err());
+        assert!(parse("cat <<EOF\n").unwrap_err().is::<IncompleteInput>());
+    }
+
+    #[test]
+    fn error_locations() {
+        assert_eq!(parse("echo a )").unwrap_err().to_string(), "Parse error: Unexpected )\n  echo a )\n         ^");
+        assert_eq!(
+            parse("ls\nif ls; then\n  x=1 2\nfi").unwrap_err().to_string(),
+            "3:7: Parse error: Unexpected argument after assignment, did you quote the value?\n    x=1 2\n        ^",
+        );
+        assert_eq!(
+            parse("echo $(ls | )").unwrap_err().to_string(),
+            "Parse error: Expected command after pipe (|)\n  echo $(ls | )\n       ^^^^^^^^",
+        );
+        let spans = |script: &str| parse_spanned(script).unwrap().into_iter().map(|(_, span)| span).collect::<Vec<_>>();
+        assert_eq!(spans("ls; echo a | wc\n\nif ls; then ls; fi # x"), vec![0..2, 4..15, 17..35]);
+    }
+
+    #[test]
+    fn pipes() {
+        assert_eq!(
+            parse("cat hello | wc").unwrap(),
+            cmd_list(pipe(lit_invocation(["cat", "hello"]), lit_invocation(["wc"])))
+        );
+        assert_eq!(
+            parse("ls|wc -l").unwrap(),
+            cmd_list(pipe(lit_invocation(["ls"]), lit_invocation(["wc", "-l"])))
+        );
+        assert_eq!(
+            parse("cat a | cat | wc").unwrap(),
+            cmd_list(pipe(pipe(lit_invocation(["cat", "a"]), lit_invocation(["cat"])), lit_invocation(["wc"])))
+        );
+        assert_eq!(
+            parse("cat a > b | wc > c").unwrap(),
+            cmd_list(pipe(lit_redirect(lit_invocation(["cat", "a"]), "b"), lit_redirect(lit_invocation(["wc"]), "c")))
+        );
+        assert!(parse("|").is_err());
+        assert!(parse("ls |").is_err());
+        assert!(parse("| wc").is_err());
+        assert!(parse("ls | | wc").is_err());
+    }
+
+    #[test]
+    fn interpolations() {
+        assert_eq!(parse("echo $x").unwrap(), cmd_list(invocation([arg([lit("echo")]), arg([lit(""), var("x")])])));
+        assert_eq!(parse("cd /$x/y").unwrap(), cmd_list(invocation([arg([lit("cd")]), arg([lit("/"), var("x"), lit("/y")])])));
+    }
+
+    #[test]
+    fn substitutions() {
+        assert_eq!(
+            parse("echo $(pwd)").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit(""), sub(lit_invocation(["pwd"]))])]))
+        );
+        assert_eq!(
+            parse("cd $(cat /user/me/lastdir)").unwrap(),
+            cmd_list(invocation([arg([lit("cd")]), arg([lit(""), sub(lit_invocation(["cat", "/user/me/lastdir"]))])]))
+        );
+        assert_eq!(
+            parse(r#"echo "$(echo $(pwd))""#).unwrap(),
+            cmd_list(invocation([
+                arg([lit("echo")]),
+                arg([lit(""), sub(invocation([arg([lit("echo")]), arg([lit(""), sub(lit_invocation(["pwd"]))])])), lit("")]),
+            ]))
+        );
+        assert_eq!(
+            parse("echo $(ls | wc)").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit(""), sub(pipe(lit_invocation(["ls"]), lit_invocation(["wc"])))])]))
+        );
+        assert_eq!(
+            parse("echo $(cd a; pwd)").unwrap(),
+            cmd_list(invocation([
+                arg([lit("echo")]),
+                arg([lit(""), Fragment::Command(list([cmd_stmt(lit_invocation(["cd", "a"])), cmd_stmt(lit_invocation(["pwd"]))]))]),
+            ]))
+        );
+        assert!(parse("echo $(echo '").is_err());
+    }
+
+    fn tilde(username: &str) -> Fragment {
+        Fragment::Tilde(username.to_owned())
+    }
+
+    fn glob(glob: &str) -> Fragment {
+        Fragment::Glob(glob.to_owned())
+    }
+
+    fn param(name: &str, operation: ParameterOperation) -> Fragment {
+        Fragment::Parameter(Parameter { name: name.to_owned(), operation })
+    }
+
+    #[test]
+    fn parameters() {
+        let echo = |fragments: Vec<Fragment>| cmd_list(invocation([arg([lit("echo")]), arg(fragments)]));
+        assert_eq!(parse("echo ${x}_a").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::Value), lit("_a")]));
+        assert_eq!(parse("echo ${#x}").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::Length)]));
+        assert_eq!(parse("echo ${#}").unwrap(), echo(vec![lit(""), param("#", ParameterOperation::Value)]));
+        assert_eq!(parse("echo ${1:-a b}").unwrap(), echo(vec![lit(""), param("1", ParameterOperation::Default(arg([lit("a"), lit(" "), lit("b")])))]));
+        assert_eq!(parse("echo ${x:=$y}").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::AssignDefault(arg([lit(""), var("y")])))]));
+        assert_eq!(parse("echo ${x:?'not set'}").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::Require(arg([lit("not set")])))]));
+        assert_eq!(parse("echo ${x##*/}").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::RemovePrefix { pattern: arg([lit(""), glob("*"), lit("/")]), longest: true })]));
+        assert_eq!(parse("echo ${x%.json}").unwrap(), echo(vec![lit(""), param("x", ParameterOperation::RemoveSuffix { pattern: arg([lit(".json")]), longest: false })]));
+        assert!(parse("echo ${}").is_err());
+        assert!(parse("echo ${1x}").is_err());
+        assert!(parse("echo ${x:+y}").is_err());
+        assert!(parse("echo ${x:-a | b}").is_err());
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(parse("x=1").unwrap(), list([assign("x", "1")]));
+        assert_eq!(parse("x = 'a b'").unwrap(), list([assign("x", "a b")]));
+        assert!(parse("x=").is_err());
+        assert!(parse("x=a b").is_err());
+        assert_eq!(
+            parse("alias ll='ls -l'").unwrap(),
+            cmd_list(invocation([arg([lit("alias")]), arg([lit("ll"), lit("="), lit("ls -l")])])),
+        );
+        assert_eq!(
+            parse("echo a=$x").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit("a"), lit("="), lit(""), var("x")])])),
+        );
+        // A = separated by whitespace only joins the words it touches
+        assert_eq!(
+            parse("echo a= $x").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit("a"), lit("=")]), arg([lit(""), var("x")])])),
+        );
+        assert_eq!(
+            parse("echo a = b =c").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit("a")]), arg([lit("=")]), arg([lit("b")]), arg([lit("="), lit("c")])])),
+        );
+        assert_eq!(
+            parse("ls | echo = b").unwrap(),
+            list([cmd_stmt(pipe(lit_invocation(["ls"]), invocation([arg([lit("echo")]), arg([lit("=")]), arg([lit("b")])])))]),
+        );
+    }
+
+    #[test]
+    fn chains() {
+        let [a, b, c] = ["a", "b", "c"].map(|s| cmd_stmt(lit_invocation([s])));
+        assert_eq!(parse("a; b").unwrap(), list([a.clone(), b.clone()]));
+        assert_eq!(parse("a;b;").unwrap(), list([a.clone(), b.clone()]));
+        assert_eq!(parse(";a;;b").unwrap(), list([a.clone(), b.clone()]));
+        assert_eq!(parse("a && b").unwrap(), list([and(a.clone(), b.clone())]));
+        assert_eq!(parse("a || b").unwrap(), list([or(a.clone(), b.clone())]));
+        assert_eq!(parse("a || b && c").unwrap(), list([and(or(a.clone(), b.clone()), c.clone())]));
+        assert_eq!(parse("a && b; c").unwrap(), list([and(a.clone(), b.clone()), c.clone()]));
+        assert_eq!(parse("x=1 && a").unwrap(), list([and(assign("x", "1"), a.clone())]));
+        assert_eq!(
+            parse("a | b && c > d").unwrap(),
+            list([and(cmd_stmt(pipe(lit_invocation(["a"]), lit_invocation(["b"]))), cmd_stmt(lit_redirect(lit_invocation(["c"]), "d")))])
+        );
+        assert!(parse("&& a").is_err());
+        assert!(parse("a ||").is_err());
+        assert!(parse("a && ; b").is_err());
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let [a, b, c] = ["a", "b", "c"].map(|s| cmd_stmt(lit_invocation([s])));
+        assert_eq!(parse("a\nb\n\nc\n").unwrap(), list([a.clone(), b.clone(), c.clone()]));
+        assert_eq!(parse("# comment\na # comment\n").unwrap(), list([a.clone()]));
+        assert_eq!(parse("a &&\n  b ||\n c").unwrap(), list([or(and(a.clone(), b.clone()), c.clone())]));
+        assert_eq!(parse("a |\nb").unwrap(), cmd_list(pipe(lit_invocation(["a"]), lit_invocation(["b"]))));
+    }
+
+    #[test]
+    fn ifs() {
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|s| cmd_stmt(lit_invocation([s])));
+        assert_eq!(
+            parse("if a; then b; fi").unwrap(),
+            list([if_(list([a.clone()]), list([b.clone()]), None)])
+        );
+        assert_eq!(
+            parse("if a\nthen\n  b\n  c\nelse\n  d\nfi").unwrap(),
+            list([if_(list([a.clone()]), list([b.clone(), c.clone()]), Some(list([d.clone()])))])
+        );
+        assert_eq!(
+            parse("if a; then b; elif c; then d; fi").unwrap(),
+            list([if_(list([a.clone()]), list([b.clone()]), Some(list([if_(list([c.clone()]), list([d.clone()]), None)])))])
+        );
+        assert_eq!(
+            parse("if a && b; then if c; then d; fi; fi; a").unwrap(),
+            list([if_(list([and(a.clone(), b.clone())]), list([if_(list([c.clone()]), list([d.clone()]), None)]), None), a.clone()])
+        );
+        assert_eq!(
+            parse("if a; then b; fi || c").unwrap(),
+            list([or(if_(list([a.clone()]), list([b.clone()]), None), c.clone())])
+        );
+        assert_eq!(parse("echo if fi").unwrap(), cmd_list(lit_invocation(["echo", "if", "fi"])));
+        assert!(parse("if a; then b").is_err());
+        assert!(parse("if a; b; fi").is_err());
+        assert!(parse("if; then b; fi").is_err());
+        assert!(parse("if a; then b; else c").is_err());
+        assert!(parse("fi").is_err());
+    }
+
+    #[test]
+    fn loops() {
+        let [a, b] = ["a", "b"].map(|s| cmd_stmt(lit_invocation([s])));
+        assert_eq!(
+            parse("for x in 1 2 3; do a; done").unwrap(),
+            list([for_("x", ["1", "2", "3"], list([a.clone()]))])
+        );
+        assert_eq!(
+            parse("for x in\ndo\ndone").unwrap(),
+            list([for_("x", [], list([]))])
+        );
+        assert_eq!(
+            parse("for user in alice bob\ndo\n  a\n  b\ndone").unwrap(),
+            list([for_("user", ["alice", "bob"], list([a.clone(), b.clone()]))])
+        );
+        assert_eq!(
+            parse("while a; do b; done").unwrap(),
+            list([while_(list([a.clone()]), list([b.clone()]))])
+        );
+        assert_eq!(
+            parse("while a\ndo\n  for x in y; do b; done\ndone").unwrap(),
+            list([while_(list([a.clone()]), list([for_("x", ["y"], list([b.clone()]))]))])
+        );
+        // Like in POSIX shells, only unquoted substitutions are split into words
+        let split = |fragment| Fragment::Split(Box::new(fragment));
+        assert_eq!(
+            parse("for f in $(ls) \"$(ls)\" a$x ${y}; do a; done").unwrap(),
+            list([Statement::For {
+                variable: "f".to_owned(),
+                values: vec![
+                    arg([lit(""), split(sub(lit_invocation(["ls"])))]),
+                    arg([lit(""), sub(lit_invocation(["ls"])), lit("")]),
+                    arg([lit("a"), split(var("x"))]),
+                    arg([lit(""), split(Fragment::Parameter(Parameter { name: "y".to_owned(), operation: ParameterOperation::Value }))]),
+                ],
+                body: list([a.clone()]),
+            }])
+        );
+        assert!(parse("for x 1 2; do a; done").is_err());
+        assert!(parse("for x in 1 2 do a; done").is_err());
+        assert!(parse("for $x in 1; do a; done").is_err());
+        assert!(parse("while a; do b").is_err());
+        assert!(parse("while; do b; done").is_err());
+        assert!(parse("done").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let [a, b] = ["a", "b"].map(|s| cmd_stmt(lit_invocation([s])));
+        assert_eq!(parse("f() { a; b; }").unwrap(), list([function("f", list([a.clone(), b.clone()]))]));
+        assert_eq!(parse("f ( )\n{\n  a\n}\nb").unwrap(), list([function("f", list([a.clone()])), b.clone()]));
+        assert_eq!(parse("f() { }").unwrap(), list([function("f", list([]))]));
+        assert_eq!(
+            parse("f() { if a; then b; fi; }").unwrap(),
+            list([function("f", list([if_(list([a.clone()]), list([b.clone()]), None)]))])
+        );
+        assert_eq!(
+            parse("reset_model() { rm -r $1; mkdir $1; }").unwrap(),
+            list([function("reset_model", list([
+                cmd_stmt(invocation([arg([lit("rm")]), arg([lit("-r")]), arg([lit(""), var("1")])])),
+                cmd_stmt(invocation([arg([lit("mkdir")]), arg([lit(""), var("1")])])),
+            ]))])
+        );
+        assert!(parse("f() { a; ").is_err());
+        assert!(parse("f() a").is_err());
+        assert!(parse("f( { a; }").is_err());
+        assert!(parse("$f() { a; }").is_err());
+        assert!(parse("}").is_err());
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(parse("mkdir /a/{b,c}/d").unwrap(), cmd_list(lit_invocation(["mkdir", "/a/b/d", "/a/c/d"])));
+        assert_eq!(parse("touch g{1..3}").unwrap(), cmd_list(lit_invocation(["touch", "g1", "g2", "g3"])));
+        assert_eq!(parse("echo {3..1} {a..e..2}").unwrap(), cmd_list(lit_invocation(["echo", "3", "2", "1", "a", "c", "e"])));
+        assert_eq!(parse("echo {08..10}").unwrap(), cmd_list(lit_invocation(["echo", "08", "09", "10"])));
+        assert_eq!(parse("echo {a,b}{1,2}").unwrap(), cmd_list(lit_invocation(["echo", "a1", "a2", "b1", "b2"])));
+        assert_eq!(parse("echo {a,{b,c}d}").unwrap(), cmd_list(lit_invocation(["echo", "a", "bd", "cd"])));
+        assert_eq!(parse("echo {,x}").unwrap(), cmd_list(lit_invocation(["echo", "", "x"])));
+        assert_eq!(parse("echo {a} a,b {a,b '{a,b}'").unwrap(), cmd_list(lit_invocation(["echo", "{a}", "a,b", "{a,b", "{a,b}"])));
+        assert_eq!(
+            parse("echo {$x,y}").unwrap(),
+            cmd_list(invocation([arg([lit("echo")]), arg([lit(""), var("x")]), arg([lit("y")])])),
+        );
+        assert_eq!(
+            parse("for x in {1..2}; do a; done").unwrap(),
+            list([for_("x", ["1", "2"], cmd_list(lit_invocation(["a"])))]),
+        );
+        assert_eq!(
+            parse("f() { a; }").unwrap(),
+            list([function("f", cmd_list(lit_invocation(["a"])))]),
+        );
+    }
+
+    #[test]
+    fn tildes() {
+        assert_eq!(
+            parse("cd ~/a ~bob '~'").unwrap(),
+            cmd_list(invocation([arg([lit("cd")]), arg([lit(""), tilde(""), lit("/a")]), arg([lit(""), tilde("bob")]), arg([lit("~")])])),
         );
     }
 
@@ -206,7 +1241,22 @@ mod tests {
         assert!(parse("'").is_err());
         assert!(parse(r#"""#).is_err());
         assert!(parse(r#" "''  "" "#).is_err());
-        assert_eq!(parse("''").unwrap(), cmd_stmt(lit_invocation([""])));
-        assert_eq!(parse(r#" "''"  "" "#).unwrap(), cmd_stmt(lit_invocation(["''", ""])));
+        assert_eq!(parse("''").unwrap(), cmd_list(lit_invocation([""])));
+        assert_eq!(parse(r#" "''"  "" "#).unwrap(), cmd_list(lit_invocation(["''", ""])));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let is_incomplete = |script: &str| parse(script).unwrap_err().is::<IncompleteInput>();
+        assert!(is_incomplete("echo 'a"));
+        assert!(is_incomplete("echo \"$(ls"));
+        assert!(is_incomplete("a &&"));
+        assert!(is_incomplete("a |\n"));
+        assert!(is_incomplete("if a; then"));
+        assert!(is_incomplete("while a\ndo b"));
+        assert!(is_incomplete("f() {\n a"));
+        assert!(!is_incomplete("&& a"));
+        assert!(!is_incomplete("a && ; b"));
+        assert!(!is_incomplete("fi"));
     }
 }
@@ -0,0 +1,156 @@
+/// Whether the text matches the glob pattern. Like in POSIX shells, `*`
+/// matches any string, `?` any single character and `[...]` any character in
+/// the set (supporting ranges like `a-z` and negation via `!` or `^`). A
+/// backslash matches the following character literally.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_chars(&pattern, &text)
+}
+
+/// Whether the pattern contains unescaped special characters, i.e. is not
+/// just a literal string.
+pub fn is_pattern(pattern: &str) -> bool {
+    let mut it = pattern.chars();
+    while let Some(c) = it.next() {
+        match c {
+            '\\' => { it.next(); },
+            '*' | '?' | '[' => return true,
+            _ => {},
+        }
+    }
+    false
+}
+
+/// Escapes the special characters in the string, such that the resulting
+/// pattern only matches the string itself.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the escapes from a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut unescaped = String::new();
+    let mut it = pattern.chars();
+    while let Some(c) = it.next() {
+        match c {
+            '\\' => unescaped.extend(it.next()),
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn matches_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', rest @ ..] => (0..=text.len()).any(|i| matches_chars(rest, &text[i..])),
+        ['?', rest @ ..] => !text.is_empty() && matches_chars(rest, &text[1..]),
+        ['[', rest @ ..] if parse_class(rest).is_some() => {
+            let (class, rest) = parse_class(rest).unwrap();
+            text.first().is_some_and(|&c| class.contains(c)) && matches_chars(rest, &text[1..])
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => text.first() == Some(c) && matches_chars(rest, &text[1..]),
+    }
+}
+
+/// A bracketed character class, e.g. `[a-z_]`.
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(start, end)| (start..=end).contains(&c)) != self.negated
+    }
+}
+
+/// Parses a character class, assuming that the opening bracket has already
+/// been consumed. Returns the class and the remaining pattern, or `None` if
+/// the bracket is not closed (in which case it is matched literally).
+fn parse_class(pattern: &[char]) -> Option<(Class, &[char])> {
+    let (negated, mut rest) = match pattern {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut ranges = Vec::new();
+    // Like in POSIX shells, a leading closing bracket is part of the set
+    let mut is_first = true;
+    loop {
+        match rest {
+            [']', after @ ..] if !is_first => return Some((Class { negated, ranges }, after)),
+            [start, '-', end, after @ ..] if *end != ']' => {
+                ranges.push((*start, *end));
+                rest = after;
+            },
+            [c, after @ ..] => {
+                ranges.push((*c, *c));
+                rest = after;
+            },
+            [] => return None,
+        }
+        is_first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, is_pattern, matches, unescape};
+
+    #[test]
+    fn literals() {
+        assert!(matches("", ""));
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abc", "abd"));
+        assert!(!matches("abc", "ab"));
+        assert!(matches(r"a\*", "a*"));
+        assert!(!matches(r"a\*", "ab"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "abc"));
+        assert!(matches("a*", "abc"));
+        assert!(matches("*.json", "scene.json"));
+        assert!(!matches("*.json", "scene.json.bak"));
+        assert!(matches("a*b*c", "aXbYc"));
+        assert!(matches("?", "a"));
+        assert!(!matches("?", ""));
+        assert!(matches("a?c", "abc"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc]", "b"));
+        assert!(!matches("[abc]", "d"));
+        assert!(matches("[a-z]1", "q1"));
+        assert!(!matches("[a-z]", "Q"));
+        assert!(matches("[!a-z]", "Q"));
+        assert!(matches("[^a]", "b"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[a", "[a"));
+    }
+
+    #[test]
+    fn escapes() {
+        for s in ["", "a", "a*b", "[x]?", r"a\b"] {
+            assert!(matches(&escape(s), s));
+            assert!(!is_pattern(&escape(s)));
+            assert_eq!(unescape(&escape(s)), s);
+        }
+        assert!(!matches(&escape("a*"), "ab"));
+        assert!(is_pattern("a*"));
+        assert!(is_pattern("[ab]"));
+        assert!(!is_pattern("a]"));
+    }
+}
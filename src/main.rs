@@ -1,22 +1,29 @@
 mod cmd;
 mod client_id;
 mod context;
+mod filter;
+mod helper;
+mod history;
 mod line;
 mod path;
+mod rc;
+mod status;
+mod value;
 
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet}, process};
 
 use anyhow::Result;
 use clap::Parser;
 use lighthouse_client::{protocol::Authentication, Lighthouse, LIGHTHOUSE_URL};
 use path::VirtualPathBuf;
-use rustyline::{config::Configurer, error::ReadlineError, DefaultEditor};
+use rustyline::{error::ReadlineError, history::FileHistory, CompletionType, Config, Editor};
 use tokio::fs;
 use url::Url;
 
-use crate::context::Context;
+use crate::{context::Context, helper::ShellHelper, status::ExitStatus};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MAX_HISTORY_SIZE: usize = 1000;
 
 #[derive(Parser)]
 #[command(about, version)]
@@ -30,11 +37,17 @@ struct Args {
     /// The server URL.
     #[arg(long, env = "LIGHTHOUSE_URL", default_value = LIGHTHOUSE_URL)]
     url: String,
+    /// Also source the .limorc in the working directory on startup.
+    #[arg(long, env = "LIMO_LOCAL_RC")]
+    local_rc: bool,
     /// Interpret/run the given command line.
     #[arg(short)]
     command: Option<String>,
     /// Path to a shell script to interpret.
     script_path: Option<String>,
+    /// Positional arguments ($1, $2, ...) to pass to the script.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    script_args: Vec<String>,
 }
 
 #[tokio::main]
@@ -44,41 +57,104 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     let auth = Authentication::new(&args.username, &args.token);
 
+    // Like in POSIX shells, $0 is the script path (or the first operand with -c)
+    let positional_args = [args.script_path.clone().unwrap_or_else(|| env!("CARGO_PKG_NAME").to_owned())]
+        .into_iter()
+        .chain(args.script_args)
+        .collect();
+
     let url = Url::parse(&args.url)?;
     let host = url.host_str().unwrap_or("?");
 
-    let ctx = Context {
+    let mut ctx = Context {
         lh: Lighthouse::connect_with_tokio_to(&args.url, auth).await?,
         cwd: VirtualPathBuf::root(),
         variables: HashMap::new(),
+        exported: HashSet::new(),
+        status: ExitStatus::SUCCESS,
+        positional_args,
+        functions: HashMap::new(),
+        call_depth: 0,
+        aliases: HashMap::new(),
+        expanding_aliases: Vec::new(),
+        history: Vec::new(),
         host: host.to_string(),
         username: args.username,
     };
 
-    if let Some(command) = args.command {
-        run_script(&command, ctx).await
+    let result = if let Some(command) = args.command {
+        run_script(&command, None, &mut ctx).await
     } else if let Some(script_path) = args.script_path {
-        let script = fs::read_to_string(script_path).await?;
-        run_script(&script, ctx).await
+        let script = fs::read_to_string(&script_path).await?;
+        run_script(&script, Some(&script_path), &mut ctx).await
     } else {
-        run_interactive(ctx).await
+        source_rc_files(args.local_rc, &mut ctx).await;
+        return run_interactive(ctx).await;
+    };
+
+    // Exit with the status of the last failing statement so callers (e.g. CI jobs) can detect failures
+    if let Err(e) = result {
+        line::report_error(&e);
+        process::exit(ctx.status.code());
     }
+
+    Ok(())
 }
 
 async fn run_interactive(mut ctx: Context) -> Result<()> {
-    let mut rl = DefaultEditor::new().unwrap();
-    rl.set_auto_add_history(true);
+    let config = Config::builder()
+        .max_history_size(MAX_HISTORY_SIZE)?
+        .completion_type(CompletionType::List)
+        .build();
+    let mut rl = Editor::<ShellHelper, FileHistory>::with_config(config)?;
+    rl.set_helper(Some(ShellHelper::new(ctx.lh.clone())));
+
+    let history_path = history::history_path(&ctx.host);
+    if let Some(history_path) = &history_path {
+        // A missing history file is expected on the first launch
+        _ = rl.load_history(history_path);
+        ctx.history = rl.history().iter().cloned().collect();
+        if let Some(parent) = history_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+    }
 
     println!("Limo {} (interactive shell)", VERSION);
 
     loop {
         let prompt = format!("{}@{}:{} $ ", ctx.username, ctx.host, ctx.cwd);
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(&ctx);
+        }
         match rl.readline(&prompt) {
             Ok(line) => {
+                let line = match line::expand_history(&line, &ctx.history) {
+                    Ok(Some(expanded)) => {
+                        // Like in POSIX shells, echo the expanded line
+                        println!("{}", expanded);
+                        expanded
+                    },
+                    Ok(None) => line,
+                    Err(e) => {
+                        line::report_error(&e);
+                        continue;
+                    },
+                };
+                if rl.add_history_entry(&line)? {
+                    ctx.history.push(line.clone());
+                    if let Some(history_path) = &history_path {
+                        if let Err(e) = rl.append_history(history_path) {
+                            eprintln!("Could not save history: {}", e);
+                        }
+                    }
+                }
                 let result = line::parse_interpret(&line, &mut ctx).await;
                 if let Err(e) = result {
-                    println!("{}", e.to_string().trim());
+                    line::report_error(&e);
                 };
+                if let Some(helper) = rl.helper_mut() {
+                    helper.invalidate_after(&line);
+                }
             },
             Err(ReadlineError::Interrupted) => {},
             Err(ReadlineError::Eof) => {
@@ -94,14 +170,25 @@ async fn run_interactive(mut ctx: Context) -> Result<()> {
     Ok(())
 }
 
-async fn run_script(script: &str, mut ctx: Context) -> Result<()> {
-    for line in script.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("#") {
-            continue
+/// Sources the startup files so their variables, functions and cwd carry over
+/// into the interactive session. Errors are reported without aborting startup.
+async fn source_rc_files(include_local: bool, ctx: &mut Context) {
+    for path in rc::rc_paths(include_local) {
+        let script = match fs::read_to_string(&path).await {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("Could not read {}: {}", path.display(), e);
+                continue;
+            },
+        };
+        // Errors are reported at their location in the file
+        if let Err(e) = run_script(&script, Some(&path.display().to_string()), ctx).await {
+            line::report_error(&e);
         }
-        line::parse_interpret(line, &mut ctx).await?;
     }
-    Ok(())
+}
+
+async fn run_script(script: &str, origin: Option<&str>, ctx: &mut Context) -> Result<()> {
+    line::parse_interpret_script(script, origin, ctx).await
 }
 
@@ -17,6 +17,11 @@ impl VirtualPathBuf {
         Self(Vec::new())
     }
 
+    /// The home directory of the given user.
+    pub fn home(username: &str) -> Self {
+        Self::from(["", "user", username])
+    }
+
     pub fn push(&mut self, path: impl AsRef<VirtualPath>) {
         let path = path.as_ref();
         let is_abs = path.is_absolute();
@@ -25,7 +30,7 @@ impl VirtualPathBuf {
             self.0.clear();
         }
 
-        for segment in path.0.into_iter() {
+        for segment in path.0.iter() {
             match segment.as_str() {
                 "." => {},
                 ".." => {
@@ -109,7 +114,7 @@ impl VirtualPath {
     }
 
     pub fn is_absolute(&self) -> bool {
-        self.0.len() >= 1 && self.0[0].is_empty()
+        !self.0.is_empty() && self.0[0].is_empty()
     }
 
     pub fn is_root(&self) -> bool {
@@ -125,7 +130,7 @@ impl VirtualPath {
     }
 
     pub fn as_str_vec(&self) -> Vec<&str> {
-        self.0.into_iter().map(|s| s.as_str()).collect()
+        self.0.iter().map(|s| s.as_str()).collect()
     }
 
     pub fn as_lh_vec(&self) -> Vec<&str> {
@@ -153,7 +158,7 @@ impl ToOwned for VirtualPath {
     type Owned = VirtualPathBuf;
 
     fn to_owned(&self) -> Self::Owned {
-        self.0.into_iter().map(|s| s.to_string()).collect()
+        self.0.iter().map(|s| s.to_string()).collect()
     }
 }
 
@@ -0,0 +1,20 @@
+use std::{env, path::PathBuf};
+
+/// The name of the startup file sourced by interactive shells.
+const RC_FILE_NAME: &str = ".limorc";
+
+/// The startup files to source before the interactive session, in order: the
+/// user's ~/.limorc, followed by a project-local .limorc in the working
+/// directory if `include_local` is set. Since any directory may contain a
+/// .limorc, the local one is opt-in. Only files that exist are returned.
+pub fn rc_paths(include_local: bool) -> Vec<PathBuf> {
+    let home_rc = home::home_dir().map(|h| h.join(RC_FILE_NAME));
+    let local_rc = env::current_dir().ok().filter(|_| include_local).map(|d| d.join(RC_FILE_NAME));
+    let mut paths: Vec<PathBuf> = home_rc.into_iter()
+        .chain(local_rc)
+        .filter(|p| p.is_file())
+        .collect();
+    // Avoid sourcing ~/.limorc twice when launched from the home directory
+    paths.dedup_by(|a, b| a.canonicalize().ok() == b.canonicalize().ok());
+    paths
+}
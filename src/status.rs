@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// The exit status of a statement, following the conventions of POSIX shells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExitStatus(i32);
+
+impl ExitStatus {
+    /// The status of a successful statement.
+    pub const SUCCESS: Self = Self(0);
+    /// The general status of a failed statement.
+    pub const FAILURE: Self = Self(1);
+    /// The status of a line that could not be parsed.
+    pub const SYNTAX_ERROR: Self = Self(2);
+    /// The status of an invocation of an unknown command.
+    pub const NOT_FOUND: Self = Self(127);
+
+    pub fn code(self) -> i32 {
+        self.0
+    }
+
+    /// The exit status of a statement that produced the given error. Errors
+    /// may carry a specific status by wrapping (or being) an `ExitStatus`,
+    /// argument parsing errors use clap's conventions (e.g. 2 for usage errors)
+    /// and all other errors map to `FAILURE`.
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(status) = error.downcast_ref::<ExitStatus>() {
+            *status
+        } else if let Some(HandledFailure(status)) = error.downcast_ref::<HandledFailure>() {
+            *status
+        } else if let Some(error) = error.downcast_ref::<clap::Error>() {
+            Self(error.exit_code())
+        } else {
+            Self::FAILURE
+        }
+    }
+
+    /// The exit status of a statement that produced the given result.
+    pub fn of_result<T>(result: &anyhow::Result<T>) -> Self {
+        match result {
+            Ok(_) => Self::SUCCESS,
+            Err(e) => Self::of(e),
+        }
+    }
+}
+
+/// The failure of a `&&` chain whose left-hand side failed. The failure has
+/// already been reported and was handled by skipping the right-hand side, so
+/// unlike other failures it does not make a script fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandledFailure(pub ExitStatus);
+
+impl fmt::Display for HandledFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for HandledFailure {}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Exited with status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Error};
+    use clap::{error::ErrorKind, Command};
+
+    use super::{ExitStatus, HandledFailure};
+
+    #[test]
+    fn statuses_of_errors() {
+        assert_eq!(ExitStatus::of(&anyhow!("Some failure")), ExitStatus::FAILURE);
+        assert_eq!(ExitStatus::of(&Error::new(ExitStatus::NOT_FOUND)), ExitStatus::NOT_FOUND);
+        assert_eq!(ExitStatus::of(&Error::new(ExitStatus::NOT_FOUND).context("Unrecognized command")), ExitStatus::NOT_FOUND);
+        assert_eq!(ExitStatus::of(&Command::new("ls").error(ErrorKind::UnknownArgument, "").into()).code(), 2);
+        assert_eq!(ExitStatus::of(&Error::new(HandledFailure(ExitStatus::NOT_FOUND))), ExitStatus::NOT_FOUND);
+        assert_eq!(ExitStatus::of_result(&Ok(())), ExitStatus::SUCCESS);
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{bail, Context as _, Result};
+use lighthouse_client::protocol::Value;
+
+/// Converts the value to text, e.g. for printing or interpolating it. Strings
+/// are used as-is, anything else is formatted like JSON.
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// Converts the value to JSON. Binary values become arrays of bytes and
+/// non-string keys are converted to text, since JSON has neither.
+pub fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Integer(n) => match n.as_i64() {
+            Some(n) => n.into(),
+            None => n.as_u64().map_or(serde_json::Value::Null, Into::into),
+        },
+        Value::F32(n) => serde_json::Number::from_f64(f64::from(*n)).map_or(serde_json::Value::Null, Into::into),
+        Value::F64(n) => serde_json::Number::from_f64(*n).map_or(serde_json::Value::Null, Into::into),
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into(),
+        Value::Binary(bytes) | Value::Ext(_, bytes) => bytes.iter().map(|&b| serde_json::Value::from(b)).collect(),
+        Value::Array(items) => items.iter().map(to_json).collect(),
+        Value::Map(entries) => entries.iter().map(|(k, v)| (to_text(k), to_json(v))).collect(),
+    }
+}
+
+/// The JSON-style name of the value's type, e.g. for error messages.
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Nil => "null",
+        Value::Boolean(_) => "boolean",
+        Value::Integer(_) | Value::F32(_) | Value::F64(_) => "number",
+        Value::String(_) => "string",
+        Value::Binary(_) => "binary",
+        Value::Array(_) => "array",
+        Value::Map(_) => "object",
+        Value::Ext(_, _) => "extension",
+    }
+}
+
+/// Merges the value into the base. Like with `>>` appending to a file, maps are
+/// merged recursively and arrays are appended to, any other value replaces the
+/// base.
+pub fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Map(base_entries), Value::Map(entries)) => {
+            for (key, value) in entries {
+                match base_entries.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, base_value)) => merge(base_value, value),
+                    None => base_entries.push((key, value)),
+                }
+            }
+        },
+        (Value::Array(base_items), Value::Array(items)) => base_items.extend(items),
+        (base, value) => *base = value,
+    }
+}
+
+/// Sets the field at the path of keys and indices to the value. Like in jq,
+/// missing objects and arrays along the way are created.
+pub fn set_path(base: &mut Value, path: &[Value], value: Value) -> Result<()> {
+    let Some((key, rest)) = path.split_first() else {
+        *base = value;
+        return Ok(());
+    };
+    if base.is_nil() {
+        *base = if key.is_str() { Value::Map(Vec::new()) } else { Value::Array(Vec::new()) };
+    }
+    let child = match (base, key) {
+        (Value::Map(entries), key @ Value::String(_)) => match entries.iter().position(|(k, _)| k == key) {
+            Some(i) => &mut entries[i].1,
+            None => {
+                entries.push((key.clone(), Value::Nil));
+                &mut entries.last_mut().unwrap().1
+            },
+        },
+        (Value::Array(items), index) if index.as_i64().is_some() => {
+            let i = index.as_i64().unwrap();
+            let i = if i < 0 { i + items.len() as i64 } else { i };
+            if i < 0 || i > items.len() as i64 {
+                bail!("Index {} is out of bounds for array of length {}", index, items.len());
+            }
+            if i as usize == items.len() {
+                items.push(Value::Nil);
+            }
+            &mut items[i as usize]
+        },
+        (base, key) => bail!("Cannot set {} key in {}", type_name(key), type_name(base)),
+    };
+    set_path(child, rest, value)
+}
+
+/// Applies a JSON merge patch (RFC 7386), i.e. merges objects recursively,
+/// removes fields set to null and replaces everything else.
+pub fn merge_patch(base: &mut Value, patch: Value) {
+    let Value::Map(patch_entries) = patch else {
+        *base = patch;
+        return;
+    };
+    if !base.is_map() {
+        *base = Value::Map(Vec::new());
+    }
+    let Value::Map(base_entries) = base else { unreachable!() };
+    for (key, value) in patch_entries {
+        let position = base_entries.iter().position(|(k, _)| *k == key);
+        match (position, value) {
+            (Some(i), Value::Nil) => { base_entries.remove(i); },
+            (None, Value::Nil) => {},
+            (Some(i), value) => merge_patch(&mut base_entries[i].1, value),
+            (None, value) => {
+                let mut new_value = Value::Nil;
+                merge_patch(&mut new_value, value);
+                base_entries.push((key, new_value));
+            },
+        }
+    }
+}
+
+/// Applies a JSON Patch (RFC 6902), i.e. an array of operations like
+/// `{"op": "replace", "path": "/config/speed", "value": 3}`. If an operation
+/// fails, the base may be partially patched.
+pub fn json_patch(base: &mut Value, patch: &Value) -> Result<()> {
+    let Value::Array(operations) = patch else {
+        bail!("JSON Patch must be an array of operations, but got {}", type_name(patch));
+    };
+    for (i, operation) in operations.iter().enumerate() {
+        apply_operation(base, operation).with_context(|| format!("Could not apply patch operation {}", i))?;
+    }
+    Ok(())
+}
+
+fn apply_operation(base: &mut Value, operation: &Value) -> Result<()> {
+    let field = |name: &str| -> Result<&Value> {
+        operation.as_map()
+            .context("Patch operations must be objects")?
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(name))
+            .map(|(_, v)| v)
+            .with_context(|| format!("Patch operation is missing {:?}", name))
+    };
+    let pointer = |name: &str| -> Result<Vec<String>> {
+        parse_pointer(field(name)?.as_str().with_context(|| format!("{:?} must be a string", name))?)
+    };
+    let op = field("op")?.as_str().context("\"op\" must be a string")?;
+    let path = pointer("path")?;
+    match op {
+        "add" => add_pointer(base, &path, field("value")?.clone())?,
+        "remove" => { remove_pointer(base, &path)?; },
+        "replace" => *get_pointer(base, &path)? = field("value")?.clone(),
+        "move" => {
+            let value = remove_pointer(base, &pointer("from")?)?;
+            add_pointer(base, &path, value)?;
+        },
+        "copy" => {
+            let value = get_pointer(base, &pointer("from")?)?.clone();
+            add_pointer(base, &path, value)?;
+        },
+        "test" => {
+            let expected = field("value")?;
+            if get_pointer(base, &path)? != expected {
+                bail!("Test failed, {} is not {}", field("path")?, expected);
+            }
+        },
+        op => bail!("Unknown patch operation {:?}", op),
+    }
+    Ok(())
+}
+
+/// Parses a JSON pointer (RFC 6901), e.g. `/config/speed`, into its tokens.
+fn parse_pointer(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(tokens) = pointer.strip_prefix('/') else {
+        bail!("JSON pointer {:?} must start with /", pointer);
+    };
+    Ok(tokens.split('/').map(|t| t.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+fn array_index(token: &str, len: usize) -> Result<usize> {
+    match token.parse::<usize>() {
+        Ok(i) if i < len && (token == "0" || !token.starts_with('0')) => Ok(i),
+        _ => bail!("Invalid index {} for array of length {}", token, len),
+    }
+}
+
+fn get_pointer<'a>(base: &'a mut Value, pointer: &[String]) -> Result<&'a mut Value> {
+    let Some((token, rest)) = pointer.split_first() else {
+        return Ok(base);
+    };
+    let child = match base {
+        Value::Map(entries) => entries.iter_mut()
+            .find(|(k, _)| k.as_str() == Some(token))
+            .map(|(_, v)| v)
+            .with_context(|| format!("No such field: {}", token))?,
+        Value::Array(items) => {
+            let i = array_index(token, items.len())?;
+            &mut items[i]
+        },
+        base => bail!("Cannot access {} in {}", token, type_name(base)),
+    };
+    get_pointer(child, rest)
+}
+
+fn add_pointer(base: &mut Value, pointer: &[String], value: Value) -> Result<()> {
+    let Some((token, parent)) = pointer.split_last() else {
+        *base = value;
+        return Ok(());
+    };
+    match get_pointer(base, parent)? {
+        Value::Map(entries) => match entries.iter_mut().find(|(k, _)| k.as_str() == Some(token)) {
+            Some((_, v)) => *v = value,
+            None => entries.push((Value::from(token.as_str()), value)),
+        },
+        Value::Array(items) if token == "-" => items.push(value),
+        Value::Array(items) => {
+            let i = array_index(token, items.len() + 1)?;
+            items.insert(i, value);
+        },
+        parent => bail!("Cannot add {} to {}", token, type_name(parent)),
+    }
+    Ok(())
+}
+
+fn remove_pointer(base: &mut Value, pointer: &[String]) -> Result<Value> {
+    let Some((token, parent)) = pointer.split_last() else {
+        bail!("Cannot remove the root");
+    };
+    Ok(match get_pointer(base, parent)? {
+        Value::Map(entries) => {
+            let i = entries.iter()
+                .position(|(k, _)| k.as_str() == Some(token))
+                .with_context(|| format!("No such field: {}", token))?;
+            entries.remove(i).1
+        },
+        Value::Array(items) => {
+            let i = array_index(token, items.len())?;
+            items.remove(i)
+        },
+        parent => bail!("Cannot remove {} from {}", token, type_name(parent)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use lighthouse_client::protocol::{to_value, Value};
+    use serde_json::json;
+
+    use super::{json_patch, merge, merge_patch, set_path, to_json, to_text};
+
+    fn merged(base: serde_json::Value, value: serde_json::Value) -> Value {
+        let mut base = to_value(base).unwrap();
+        merge(&mut base, to_value(value).unwrap());
+        base
+    }
+
+    #[test]
+    fn texts() {
+        assert_eq!(to_text(&Value::from("a \"b\"")), "a \"b\"");
+        assert_eq!(to_text(&Value::from(42)), "42");
+        assert_eq!(to_text(&to_value(json!({"x": ["a"]})).unwrap()), r#"{"x": ["a"]}"#);
+    }
+
+    #[test]
+    fn jsons() {
+        let value = Value::Map(vec![(Value::from("frame"), Value::Binary(vec![1, 2])), (Value::from(3), Value::from(1.5))]);
+        assert_eq!(to_json(&value), json!({"frame": [1, 2], "3": 1.5}));
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(merged(json!(1), json!("a")), to_value(json!("a")).unwrap());
+        assert_eq!(merged(json!({"x": 1}), json!([1])), to_value(json!([1])).unwrap());
+        assert_eq!(merged(json!(null), json!({"x": 1})), to_value(json!({"x": 1})).unwrap());
+    }
+
+    #[test]
+    fn maps_and_arrays() {
+        assert_eq!(
+            merged(json!({"x": 1, "y": {"a": [1], "b": 2}}), json!({"y": {"a": [2, 3], "c": 4}, "z": 5})),
+            to_value(json!({"x": 1, "y": {"a": [1, 2, 3], "b": 2, "c": 4}, "z": 5})).unwrap(),
+        );
+        assert_eq!(merged(json!([1, {"x": 2}]), json!([{"x": 3}])), to_value(json!([1, {"x": 2}, {"x": 3}])).unwrap());
+    }
+
+    fn patched(base: serde_json::Value, patch: impl FnOnce(&mut Value)) -> serde_json::Value {
+        let mut base = to_value(base).unwrap();
+        patch(&mut base);
+        to_json(&base)
+    }
+
+    #[test]
+    fn paths() {
+        let path = |keys: serde_json::Value| to_value(keys).unwrap().as_array().unwrap().clone();
+        assert_eq!(patched(json!({"config": {"speed": 1}}), |v| set_path(v, &path(json!(["config", "speed"])), Value::from(3)).unwrap()), json!({"config": {"speed": 3}}));
+        assert_eq!(patched(json!({}), |v| set_path(v, &path(json!(["a", "b"])), Value::from(true)).unwrap()), json!({"a": {"b": true}}));
+        assert_eq!(patched(json!([1, 2]), |v| set_path(v, &path(json!([-1])), Value::from(5)).unwrap()), json!([1, 5]));
+        assert!(set_path(&mut Value::from(1), &path(json!(["a"])), Value::Nil).is_err());
+        assert!(set_path(&mut Value::Array(Vec::new()), &path(json!([3])), Value::Nil).is_err());
+    }
+
+    #[test]
+    fn merge_patches() {
+        let merge_patched = |base, patch| patched(base, |v| merge_patch(v, to_value(patch).unwrap()));
+        assert_eq!(
+            merge_patched(json!({"a": "b", "c": {"d": "e", "f": "g"}}), json!({"a": "z", "c": {"f": null}})),
+            json!({"a": "z", "c": {"d": "e"}}),
+        );
+        assert_eq!(merge_patched(json!({"a": [1]}), json!({"a": [2], "b": {"c": null}})), json!({"a": [2], "b": {}}));
+        assert_eq!(merge_patched(json!({"a": 1}), json!(["x"])), json!(["x"]));
+    }
+
+    #[test]
+    fn json_patches() {
+        let json_patched = |base, patch| -> anyhow::Result<serde_json::Value> {
+            let mut base = to_value(base).unwrap();
+            json_patch(&mut base, &to_value(patch).unwrap())?;
+            Ok(to_json(&base))
+        };
+        assert_eq!(
+            json_patched(json!({"config": {"speed": 1}, "list": [1, 2], "a/b": 0}), json!([
+                {"op": "test", "path": "/config/speed", "value": 1},
+                {"op": "replace", "path": "/config/speed", "value": 3},
+                {"op": "add", "path": "/list/1", "value": 9},
+                {"op": "add", "path": "/list/-", "value": 4},
+                {"op": "remove", "path": "/list/0"},
+                {"op": "copy", "from": "/config", "path": "/backup"},
+                {"op": "move", "from": "/a~1b", "path": "/moved"},
+            ])).unwrap(),
+            json!({"config": {"speed": 3}, "list": [9, 2, 4], "backup": {"speed": 3}, "moved": 0}),
+        );
+        assert!(json_patched(json!({"a": 1}), json!([{"op": "test", "path": "/a", "value": 2}])).is_err());
+        assert!(json_patched(json!({"a": 1}), json!([{"op": "replace", "path": "/b", "value": 2}])).is_err());
+        assert!(json_patched(json!([1]), json!([{"op": "remove", "path": "/01"}])).is_err());
+        assert!(json_patched(json!({}), json!({"op": "add"})).is_err());
+    }
+}